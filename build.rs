@@ -0,0 +1,38 @@
+//! Captures the git commit and build time for the `/version` endpoint
+use std::{
+    env,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+fn main() {
+    // prefer an explicitly provided commit (e.g. a Docker build arg), as the
+    // build may not run inside a git checkout
+    let git_commit = env::var("SOMMSTATS_GIT_COMMIT")
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .or_else(|| {
+            Command::new("git")
+                .args(["rev-parse", "HEAD"])
+                .output()
+                .ok()
+                .filter(|o| o.status.success())
+                .and_then(|o| String::from_utf8(o.stdout).ok())
+                .map(|s| s.trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+    let build_timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    println!("cargo:rustc-env=SOMMSTATS_GIT_COMMIT={git_commit}");
+    println!("cargo:rustc-env=SOMMSTATS_BUILD_TIMESTAMP={build_timestamp}");
+    println!("cargo:rerun-if-env-changed=SOMMSTATS_GIT_COMMIT");
+    // rebuild when the checked out commit changes
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    println!("cargo:rerun-if-changed=.git/packed-refs");
+    println!("cargo:rerun-if-changed=build.rs");
+}
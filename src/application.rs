@@ -1,26 +1,148 @@
 //! SommStats Abscissa Application
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
 
-use crate::{commands::EntryPoint, config::SommStatsConfig};
+use crate::{
+    client::ConnectionSettings,
+    commands::{EntryPoint, LogFormat},
+    config::SommStatsConfig,
+    health::EndpointHealth,
+    history::SupplySample,
+    log_dedup::LogDeduper,
+    query::EndpointProbe,
+    selector::EndpointSelector,
+    source::Observed,
+};
 use abscissa_core::{
     application::{self, AppCell},
-    config::{self, CfgCell},
-    trace, Application, FrameworkError, StandardPaths,
+    config::{self, CfgCell, Configurable},
+    terminal::component::Terminal,
+    trace, Application, Component, FrameworkError, FrameworkErrorKind, StandardPaths,
 };
-use abscissa_tokio::tokio::sync::Mutex;
+use abscissa_tokio::tokio::sync::RwLock;
 use lazy_static::lazy_static;
+use tonic::transport::Channel;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 
-pub type Cache<T> = Arc<Mutex<T>>;
+pub type Cache<T> = Arc<RwLock<T>>;
 
 pub const USOMM: &str = "usomm";
+/// Bech32 prefix of Sommelier account addresses
+pub const ACCOUNT_PREFIX: &str = "somm";
+/// Decimal places between usomm and SOMM
+pub const SOMM_DECIMALS: u32 = 6;
 
 lazy_static! {
     /// Balances cache, where each key is the ID of the balance, either an address in the case of
     /// vesting accounts, or a designation such as "communitypool" or "bonded" in the case of
     /// the community pool and total bonded token balances. Addresses that are not the foundation
     /// address can be safely assumed to be vesting addresses.
-    pub static ref BALANCES: Cache<HashMap<String, u64>> = Arc::new(Mutex::new(HashMap::new()));
+    pub static ref BALANCES: Cache<HashMap<String, u64>> = Arc::new(RwLock::new(HashMap::new()));
+
+    /// When each entry in [`BALANCES`] was last refreshed from chain. Balances restored from a
+    /// snapshot have no entry until they are refreshed.
+    pub static ref BALANCES_UPDATED_AT: Cache<HashMap<String, SystemTime>> =
+        Arc::new(RwLock::new(HashMap::new()));
+
+    /// Latest block height observed when each entry in [`BALANCES`] was last refreshed. Heights
+    /// are best effort, so a balance may have been refreshed without one.
+    pub static ref BALANCES_HEIGHT: Cache<HashMap<String, u64>> =
+        Arc::new(RwLock::new(HashMap::new()));
+
+    /// Consecutive failure counts for each gRPC endpoint, used to skip degraded endpoints
+    pub static ref ENDPOINT_HEALTH: Cache<EndpointHealth> =
+        Arc::new(RwLock::new(EndpointHealth::default()));
+
+    /// Last connectivity probe of the gRPC endpoints, reused by `/healthz` for a few seconds
+    pub static ref ENDPOINT_PROBE: Cache<Option<EndpointProbe>> = Arc::new(RwLock::new(None));
+
+    /// Order in which healthy gRPC endpoints are tried
+    pub static ref ENDPOINT_SELECTOR: Cache<EndpointSelector> =
+        Arc::new(RwLock::new(EndpointSelector::default()));
+
+    /// Repeated query errors suppressed from the logs
+    pub static ref LOG_DEDUPER: LogDeduper = LogDeduper::default();
+
+    /// Balances of arbitrary addresses looked up through the API, with the time they were queried
+    pub static ref LOOKUP_BALANCES: Cache<HashMap<String, (u64, Instant)>> =
+        Arc::new(RwLock::new(HashMap::new()));
+
+    /// Recent raw query results keyed by endpoint, method and arguments, with the time they were
+    /// queried. Lets near-simultaneous identical queries share one round trip.
+    pub static ref QUERY_RESULTS: Cache<HashMap<String, (Observed, Instant)>> =
+        Arc::new(RwLock::new(HashMap::new()));
+
+    /// Balances queried at past block heights, oldest first. State at a past height is immutable
+    /// so these never expire, only the oldest are evicted.
+    pub static ref HISTORICAL_BALANCES: Cache<VecDeque<(u64, HashMap<String, u64>)>> =
+        Arc::new(RwLock::new(VecDeque::new()));
+
+    /// Circulating supply samples recorded with each cache snapshot, oldest first
+    pub static ref SUPPLY_HISTORY: Cache<VecDeque<SupplySample>> =
+        Arc::new(RwLock::new(VecDeque::new()));
+
+    /// Keepalive settings applied when connecting to a gRPC endpoint
+    pub static ref CONNECTION_SETTINGS: Cache<ConnectionSettings> =
+        Arc::new(RwLock::new(ConnectionSettings::default()));
+
+    /// gRPC connections keyed by endpoint, reused across poll cycles
+    pub static ref CHANNELS: Cache<HashMap<String, Channel>> = Arc::new(RwLock::new(HashMap::new()));
+
+    /// Caches of the chains configured under `[chains]`, created on first use
+    static ref CHAIN_STATES: std::sync::Mutex<HashMap<String, ChainState>> =
+        std::sync::Mutex::new(HashMap::new());
+}
+
+/// The caches kept separately for each chain. The default chain's are the [`BALANCES`],
+/// [`BALANCES_UPDATED_AT`], [`BALANCES_HEIGHT`] and [`HISTORICAL_BALANCES`] statics.
+#[derive(Clone, Debug, Default)]
+pub struct ChainState {
+    /// Name of the chain under `[chains]`, `None` for the default chain
+    pub name: Option<String>,
+    pub balances: Cache<HashMap<String, u64>>,
+    pub updated_at: Cache<HashMap<String, SystemTime>>,
+    pub heights: Cache<HashMap<String, u64>>,
+    pub historical: Cache<VecDeque<(u64, HashMap<String, u64>)>>,
+}
+
+impl ChainState {
+    /// The caches of the chain `name`, or of the default chain for `None`
+    pub fn get(name: Option<&str>) -> Self {
+        let Some(name) = name else {
+            return Self {
+                name: None,
+                balances: BALANCES.clone(),
+                updated_at: BALANCES_UPDATED_AT.clone(),
+                heights: BALANCES_HEIGHT.clone(),
+                historical: HISTORICAL_BALANCES.clone(),
+            };
+        };
+
+        CHAIN_STATES
+            .lock()
+            .unwrap()
+            .entry(name.to_string())
+            .or_insert_with(|| Self {
+                name: Some(name.to_string()),
+                ..Default::default()
+            })
+            .clone()
+    }
+
+    /// The caches of the chain `config` was derived for with [`SommStatsConfig::chain`]
+    pub fn of(config: &SommStatsConfig) -> Self {
+        Self::get(config.chain.as_deref())
+    }
+
+    /// The caches of every chain under `[chains]` that has been used
+    pub fn named() -> Vec<ChainState> {
+        CHAIN_STATES.lock().unwrap().values().cloned().collect()
+    }
 }
 
 /// Application state
@@ -34,6 +156,9 @@ pub struct SommStatsApp {
 
     /// Application state.
     state: application::State<Self>,
+
+    /// Config file the app was started with, if any
+    config_path: Option<PathBuf>,
 }
 
 /// Initialize a new application instance.
@@ -45,6 +170,7 @@ impl Default for SommStatsApp {
         Self {
             config: CfgCell::default(),
             state: application::State::default(),
+            config_path: None,
         }
     }
 }
@@ -75,7 +201,19 @@ impl Application for SommStatsApp {
     /// beyond the default ones provided by the framework, this is the place
     /// to do so.
     fn register_components(&mut self, command: &Self::Cmd) -> Result<(), FrameworkError> {
-        let mut framework_components = self.framework_components(command)?;
+        self.config_path = command.config_path();
+        let mut framework_components = match (command.log_format, command.prints_to_stdout()) {
+            (LogFormat::Text, false) => self.framework_components(command)?,
+            // abscissa's tracing component only emits text to stdout, so JSON logging and
+            // commands whose stdout is their output get their own subscriber, and only the
+            // terminal component is registered
+            (log_format, prints_to_stdout) => {
+                init_logging(&log_filter(command), log_format, prints_to_stdout)?;
+                let terminal: Box<dyn Component<Self>> =
+                    Box::new(Terminal::new(self.term_colors(command)));
+                vec![terminal]
+            }
+        };
         let mut app_components = self.state.components_mut();
         framework_components.push(Box::new(abscissa_tokio::TokioComponent::new()?));
         app_components.register(framework_components)
@@ -87,6 +225,8 @@ impl Application for SommStatsApp {
     /// time in app lifecycle when configuration would be loaded if
     /// possible.
     fn after_config(&mut self, config: Self::Cfg) -> Result<(), FrameworkError> {
+        crate::config::validate(&config)?;
+
         // Configure components
         let mut components = self.state.components_mut();
         components.after_config(&config)?;
@@ -96,19 +236,48 @@ impl Application for SommStatsApp {
 
     /// Get tracing configuration from command-line options
     fn tracing_config(&self, command: &EntryPoint) -> trace::Config {
-        if command.verbose {
-            trace::Config::verbose()
-        } else {
-            match std::env::var("RUST_LOG") {
-                Ok(val) => {
-                    if !val.is_empty() {
-                        val.into()
-                    } else {
-                        trace::Config::default()
-                    }
-                }
-                Err(_) => trace::Config::default(),
-            }
-        }
+        log_filter(command).into()
+    }
+}
+
+impl SommStatsApp {
+    /// Config file the app was started with, which is re-read when reloading the config
+    pub fn config_path(&self) -> Option<PathBuf> {
+        self.config_path.clone()
+    }
+}
+
+/// Log filter from command-line options, falling back to `RUST_LOG` and then `info`
+fn log_filter(command: &EntryPoint) -> String {
+    if command.verbose {
+        return "debug".to_string();
     }
+
+    match std::env::var("RUST_LOG") {
+        Ok(val) if !val.is_empty() => val,
+        _ => "info".to_string(),
+    }
+}
+
+/// Installs a global subscriber that writes newline-delimited JSON including the current span and
+/// its parents, or text, to stdout or stderr
+fn init_logging(filter: &str, log_format: LogFormat, stderr: bool) -> Result<(), FrameworkError> {
+    let writer = if stderr {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(writer)
+        .with_env_filter(filter);
+    let result = match log_format {
+        LogFormat::Json => subscriber
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .try_init(),
+        LogFormat::Text => subscriber.try_init(),
+    };
+
+    result.map_err(|e| FrameworkErrorKind::ComponentError.context(e).into())
 }
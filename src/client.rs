@@ -0,0 +1,169 @@
+//! Pooled gRPC connections
+//!
+//! A connection is established once per endpoint and shared by every query against it, instead of
+//! reconnecting on each poll. ocular's `QueryClient` can't be shared between tasks, so queries
+//! build the module query client they need on top of a pooled [`Channel`], which is cheap to clone.
+
+use std::time::Duration;
+
+use abscissa_core::tracing::log::{debug, info};
+use eyre::{Result, WrapErr};
+use ocular::{
+    cosmrs::proto::cosmos::base::tendermint::v1beta1::service_client::ServiceClient,
+    query::{AuthQueryClient, BankQueryClient, DistributionQueryClient, StakingQueryClient},
+};
+use tonic::{
+    metadata::{MetadataMap, MetadataValue},
+    transport::{Channel, Endpoint},
+    Code, Request, Status,
+};
+
+use crate::{
+    application::{CHANNELS, CONNECTION_SETTINGS},
+    config::GrpcSection,
+};
+
+/// HTTP/2 keepalive settings applied to new connections
+#[derive(Clone, Copy, Debug)]
+pub struct ConnectionSettings {
+    pub keepalive_interval: Option<Duration>,
+    pub keepalive_timeout: Duration,
+    pub keepalive_while_idle: bool,
+}
+
+impl Default for ConnectionSettings {
+    fn default() -> Self {
+        Self::from(&GrpcSection::default())
+    }
+}
+
+impl From<&GrpcSection> for ConnectionSettings {
+    fn from(grpc: &GrpcSection) -> Self {
+        Self {
+            keepalive_interval: (grpc.keepalive_interval_secs > 0)
+                .then(|| Duration::from_secs(grpc.keepalive_interval_secs)),
+            keepalive_timeout: Duration::from_secs(grpc.keepalive_timeout_secs),
+            keepalive_while_idle: grpc.keepalive_while_idle,
+        }
+    }
+}
+
+impl ConnectionSettings {
+    /// Applies the settings to an endpoint before it connects
+    pub fn apply(&self, endpoint: Endpoint) -> Endpoint {
+        let endpoint = endpoint
+            .keep_alive_timeout(self.keepalive_timeout)
+            .keep_alive_while_idle(self.keepalive_while_idle);
+
+        match self.keepalive_interval {
+            Some(interval) => endpoint.http2_keep_alive_interval(interval),
+            None => endpoint,
+        }
+    }
+}
+
+/// gRPC metadata key the Cosmos SDK reads the height to query state at from
+pub const BLOCK_HEIGHT_METADATA: &str = "x-cosmos-block-height";
+
+/// Wraps a query message in a request for the state at `height`, or at the latest height if
+/// `None`. Nodes that have pruned the height fail the query.
+pub fn at_height<T>(message: T, height: Option<u64>) -> Request<T> {
+    let mut request = Request::new(message);
+    if let Some(height) = height {
+        request
+            .metadata_mut()
+            .insert(BLOCK_HEIGHT_METADATA, MetadataValue::from(height));
+    }
+
+    request
+}
+
+/// Height a node reported answering a query at, from the response metadata
+pub fn response_height(metadata: &MetadataMap) -> Option<u64> {
+    metadata
+        .get(BLOCK_HEIGHT_METADATA)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// Returns the pooled channel for the endpoint, connecting if there isn't one yet
+pub async fn channel(endpoint: &str) -> Result<Channel> {
+    if let Some(channel) = CHANNELS.read().await.get(endpoint) {
+        return Ok(channel.clone());
+    }
+
+    debug!("connecting to {}", endpoint);
+    let settings = *CONNECTION_SETTINGS.read().await;
+    let channel = settings
+        .apply(
+            Channel::from_shared(endpoint.to_string())
+                .wrap_err_with(|| format!("invalid endpoint {endpoint}"))?,
+        )
+        .connect()
+        .await
+        .wrap_err_with(|| format!("failed to connect to {endpoint}"))?;
+    CHANNELS
+        .write()
+        .await
+        .insert(endpoint.to_string(), channel.clone());
+
+    Ok(channel)
+}
+
+/// Drops the pooled channel for the endpoint if the status indicates the connection itself failed,
+/// so the next query reconnects. tonic reports transport failures as `Unavailable`; errors returned
+/// by the node over a healthy connection, including `Unknown` application errors, leave the channel
+/// in place.
+pub async fn evict_on_transport_error(endpoint: &str, status: &Status) {
+    if status.code() == Code::Unavailable {
+        info!(
+            "dropping connection to {} after error: {}",
+            endpoint, status
+        );
+        CHANNELS.write().await.remove(endpoint);
+    }
+}
+
+pub async fn auth_client(endpoint: &str) -> Result<AuthQueryClient> {
+    Ok(AuthQueryClient::new(channel(endpoint).await?))
+}
+
+pub async fn bank_client(endpoint: &str) -> Result<BankQueryClient> {
+    Ok(BankQueryClient::new(channel(endpoint).await?))
+}
+
+pub async fn distribution_client(endpoint: &str) -> Result<DistributionQueryClient> {
+    Ok(DistributionQueryClient::new(channel(endpoint).await?))
+}
+
+pub type TendermintQueryClient = ServiceClient<Channel>;
+
+pub async fn tendermint_client(endpoint: &str) -> Result<TendermintQueryClient> {
+    Ok(TendermintQueryClient::new(channel(endpoint).await?))
+}
+
+pub async fn staking_client(endpoint: &str) -> Result<StakingQueryClient> {
+    Ok(StakingQueryClient::new(channel(endpoint).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use assay::assay;
+
+    use super::*;
+
+    #[assay]
+    async fn evicts_only_on_transport_errors() {
+        let endpoint = "http://localhost:9090";
+        let channel = Endpoint::from_static(endpoint).connect_lazy();
+        CHANNELS.write().await.insert(endpoint.to_string(), channel);
+
+        evict_on_transport_error(endpoint, &Status::unknown("account not found")).await;
+        assert!(CHANNELS.read().await.contains_key(endpoint));
+
+        evict_on_transport_error(endpoint, &Status::unavailable("connection refused")).await;
+        assert!(!CHANNELS.read().await.contains_key(endpoint));
+    }
+}
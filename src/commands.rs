@@ -2,21 +2,23 @@
 //!
 //! This is where you specify the subcommands of your application.
 //!
-//! The default application comes with two subcommands:
+//! The application comes with these subcommands:
 //!
 //! - `start`: launches the application
+//! - `check`: queries the balances once and prints the circulating supply
 //! - `--version`: print application version
 //!
 //! See the `impl Configurable` below for how to specify the path to the
 //! application's configuration file.
 
+mod check;
 mod start;
 
-use self::start::StartCmd;
-use crate::config::SommStatsConfig;
+use self::{check::CheckCmd, start::StartCmd};
+use crate::config::{self, SommStatsConfig};
 use abscissa_core::{Command, Configurable, FrameworkError, Runnable};
 use clap::Parser;
-use std::path::PathBuf;
+use std::{path::PathBuf, str::FromStr};
 
 /// SommStats Configuration Filename
 pub const CONFIG_FILE: &str = "sommelier_api.toml";
@@ -27,6 +29,8 @@ pub const CONFIG_FILE: &str = "sommelier_api.toml";
 pub enum SommStatsCmd {
     /// The `start` subcommand
     Start(StartCmd),
+    /// The `check` subcommand
+    Check(CheckCmd),
 }
 
 /// Entry point for the application. It needs to be a struct to allow using subcommands!
@@ -43,6 +47,38 @@ pub struct EntryPoint {
     /// Use the specified config file
     #[clap(short, long)]
     pub config: Option<String>,
+
+    /// Log output format
+    #[clap(long, default_value = "text")]
+    pub log_format: LogFormat,
+}
+
+/// Log output formats
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LogFormat {
+    /// Human-readable text
+    Text,
+    /// Newline-delimited JSON
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format {s:?}, expected text or json")),
+        }
+    }
+}
+
+impl EntryPoint {
+    /// Whether the subcommand writes its output to stdout, in which case logs go to stderr
+    pub fn prints_to_stdout(&self) -> bool {
+        matches!(self.cmd, SommStatsCmd::Check(_))
+    }
 }
 
 impl Runnable for EntryPoint {
@@ -71,18 +107,9 @@ impl Configurable<SommStatsConfig> for EntryPoint {
         }
     }
 
-    /// Apply changes to the config after it's been loaded, e.g. overriding
-    /// values in a config file using command-line options.
-    ///
-    /// This can be safely deleted if you don't want to override config
-    /// settings from command-line options.
+    /// Apply changes to the config after it's been loaded. Environment variables take precedence
+    /// over the config file, which takes precedence over the defaults.
     fn process_config(&self, config: SommStatsConfig) -> Result<SommStatsConfig, FrameworkError> {
-        // match &self.cmd {
-        //     // If you don't need special overrides for some
-        //     // subcommands, you can just use a catch all
-        //     _ => Ok(config),
-        // }
-
-        Ok(config)
+        config::apply_env_overrides(config, |name| std::env::var(name).ok())
     }
 }
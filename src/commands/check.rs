@@ -0,0 +1,96 @@
+//! `check` subcommand - queries every balance once and prints the circulating supply
+
+use std::collections::HashMap;
+
+use crate::application::{BALANCES, CONNECTION_SETTINGS, ENDPOINT_HEALTH, ENDPOINT_SELECTOR};
+use crate::config::SommStatsConfig;
+use crate::prelude::*;
+use crate::query::{
+    update_community_pool_balance, update_foundation_balance, update_vesting_balances,
+};
+use crate::server::supply_breakdown;
+use crate::source::GrpcSource;
+
+use abscissa_core::config::Override;
+use abscissa_core::{Command, FrameworkError, Runnable};
+use clap::Parser;
+
+/// `check` subcommand
+///
+/// Queries every balance needed for the circulating supply once, prints the supply breakdown to
+/// stdout as JSON and exits. The exit code is non-zero if any query failed or the circulating
+/// supply couldn't be calculated. The HTTP server and pollers aren't started.
+#[derive(Command, Debug, Parser)]
+pub struct CheckCmd {}
+
+impl Runnable for CheckCmd {
+    fn run(&self) {
+        let config = APP.config();
+
+        let passed = abscissa_tokio::run(&APP, async {
+            ENDPOINT_HEALTH.write().await.configure(&config.grpc);
+            ENDPOINT_SELECTOR.write().await.configure(&config.grpc);
+            *CONNECTION_SETTINGS.write().await = (&config.grpc).into();
+
+            let mut passed = query_balances(&config).await;
+            let breakdown = supply_breakdown(&config, &*BALANCES.read().await);
+            if breakdown.circulating_supply.is_none() {
+                status_err!("failed to calculate the circulating supply");
+                passed = false;
+            }
+            match serde_json::to_string_pretty(&breakdown) {
+                Ok(json) => println!("{json}"),
+                Err(e) => {
+                    status_err!("failed to serialize supply breakdown: {}", e);
+                    passed = false;
+                }
+            }
+
+            passed
+        })
+        .unwrap_or_else(|e| {
+            status_err!("executor exited with error: {}", e);
+            std::process::exit(1)
+        });
+
+        if !passed {
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Queries every balance subtracted from the total supply once, returning whether all of the
+/// queries succeeded. Failures are reported on stderr.
+async fn query_balances(config: &SommStatsConfig) -> bool {
+    let mut passed = true;
+    for address in config.accounting.foundation_accounts() {
+        if let Err(e) = update_foundation_balance(&GrpcSource, config, &address).await {
+            status_err!("{:?}", e);
+            passed = false;
+        }
+    }
+    if let Err(e) = update_community_pool_balance(&GrpcSource, config).await {
+        status_err!("{:?}", e);
+        passed = false;
+    }
+    // vesting failures are logged by the update itself and show up as missing balances
+    update_vesting_balances(&GrpcSource, config, &mut HashMap::new()).await;
+    let balances = BALANCES.read().await;
+    if let Some(missing) = config
+        .accounting
+        .vesting_accounts()
+        .into_iter()
+        .find(|a| !balances.contains_key(a))
+    {
+        status_err!("failed to query vesting balance of {}", missing);
+        passed = false;
+    }
+
+    passed
+}
+
+impl Override<SommStatsConfig> for CheckCmd {
+    fn override_config(&self, config: SommStatsConfig) -> Result<SommStatsConfig, FrameworkError> {
+        Ok(config)
+    }
+}
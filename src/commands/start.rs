@@ -1,19 +1,24 @@
 //! `start` subcommand - example of how to write a subcommand
 
-use std::net::SocketAddr;
+use std::path::Path;
 
-use crate::config::{self, SommStatsConfig};
+use crate::application::{CONNECTION_SETTINGS, ENDPOINT_HEALTH, ENDPOINT_SELECTOR, LOG_DEDUPER};
+use crate::config::SommStatsConfig;
 /// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
 /// accessors along with logging macros. Customize as you see fit.
 use crate::prelude::*;
-use crate::query::poll_vesting_balance;
-use crate::query::{poll_community_pool_balance, poll_foundation_balance};
-use crate::server::listen;
+use crate::query::{poll_balances, self_test};
+use crate::reload::reload_on_sighup;
+use crate::server::{listen, shutdown_signal};
+use crate::snapshot::{poll_snapshot, take_cache_snapshot, try_load_snapshot};
+use crate::source::GrpcSource;
+use crate::telemetry;
 
 use abscissa_core::config::Override;
 use abscissa_core::{Command, FrameworkError, Runnable};
-use abscissa_tokio::tokio::join;
+use abscissa_tokio::tokio::{self, join};
 use clap::Parser;
+use futures::future::join_all;
 
 /// `start` subcommand
 ///
@@ -29,18 +34,71 @@ impl Runnable for StartCmd {
     /// Start the application.
     fn run(&self) {
         let config = APP.config();
-        config::validate(&config);
+
+        if let Err(e) = telemetry::install_recorder() {
+            status_err!("failed to install metrics recorder: {}", e);
+            std::process::exit(1);
+        }
 
         abscissa_tokio::run(&APP, async {
-            let addr: SocketAddr = format!("{}:{}", config.server.address, config.server.port)
-                .parse()
-                .expect("failed to parse socket address");
-            let _ = join!(
-                poll_vesting_balance(),
-                poll_foundation_balance(),
-                poll_community_pool_balance(),
-                listen(addr)
-            );
+            ENDPOINT_HEALTH.write().await.configure(&config.grpc);
+            ENDPOINT_SELECTOR.write().await.configure(&config.grpc);
+            LOG_DEDUPER.configure(&config.grpc);
+            *CONNECTION_SETTINGS.write().await = (&config.grpc).into();
+            if let Some(total_supply) = config.accounting.total_supply_override {
+                warn!(
+                    "TOTAL SUPPLY OVERRIDE ACTIVE: calculating circulating supply from a configured \
+                     total supply of {}usomm instead of the built-in value. Remove \
+                     total_supply_override once the incident is resolved",
+                    total_supply
+                );
+            }
+            if !config.accounting.exclude_from_circulating.is_empty() {
+                info!(
+                    "excluding vesting accounts from the circulating supply calculation: {}",
+                    config.accounting.exclude_from_circulating.join(", ")
+                );
+            }
+            if let Err(e) = try_load_snapshot(Path::new(&config.cache.snapshot_file)).await {
+                warn!("failed to load cache snapshot: {:?}", e);
+            }
+
+            if !startup_self_test(&config).await && config.grpc.strict_startup {
+                status_err!("endpoint self-test failed and strict_startup is enabled");
+                std::process::exit(1);
+            }
+
+            let addr = config
+                .server
+                .socket_addr()
+                .expect("server address is validated when the config is loaded");
+            let pollers = async {
+                join!(
+                    poll_balances(&GrpcSource, None),
+                    join_all(
+                        config
+                            .chains
+                            .keys()
+                            .map(|name| poll_balances(&GrpcSource, Some(name)))
+                    ),
+                    poll_snapshot(),
+                    reload_on_sighup(),
+                )
+            };
+
+            // the pollers run until the server has drained and are dropped with it
+            tokio::select! {
+                _ = pollers => {}
+                result = listen(addr, shutdown_signal()) => {
+                    if let Err(e) = result {
+                        error!("server exited with error: {:?}", e);
+                    }
+                }
+            }
+
+            if let Err(e) = take_cache_snapshot(Path::new(&config.cache.snapshot_file)).await {
+                warn!("failed to take final cache snapshot: {:?}", e);
+            }
         })
         .unwrap_or_else(|e| {
             status_err!("executor exited with error: {}", e);
@@ -49,6 +107,30 @@ impl Runnable for StartCmd {
     }
 }
 
+/// Queries every endpoint of every chain once and logs the result, returning whether all of them
+/// passed
+async fn startup_self_test(config: &SommStatsConfig) -> bool {
+    let chains = config.chains.keys().filter_map(|name| config.chain(name));
+    let mut passed = true;
+    for config in std::iter::once(config.clone()).chain(chains) {
+        let address = config.accounting.foundation_accounts().remove(0);
+        for (endpoint, result) in self_test(&GrpcSource, &config.grpc, &address).await {
+            match result {
+                Ok(height) => info!(
+                    "endpoint {} passed self-test at height {}",
+                    endpoint, height
+                ),
+                Err(e) => {
+                    passed = false;
+                    warn!("endpoint {} failed self-test: {:?}", endpoint, e);
+                }
+            }
+        }
+    }
+
+    passed
+}
+
 impl Override<SommStatsConfig> for StartCmd {
     // Process the given command line options, overriding settings from
     // a configuration file using explicit flags taken from command-line
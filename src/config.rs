@@ -4,21 +4,215 @@
 //! application's configuration file and/or command-line options
 //! for specifying it.
 
+use std::{
+    collections::BTreeMap,
+    net::{AddrParseError, IpAddr, SocketAddr},
+};
+
+use abscissa_core::{FrameworkError, FrameworkErrorKind};
+use axum::http::Uri;
+use ocular::cosmrs::AccountId;
 use serde::{Deserialize, Serialize};
+use tonic::transport::Endpoint;
+
+use crate::{
+    accounting::{FOUNDATION_ADDRESS, FOUNDATION_ADDRESS_2, TOTAL_USOMM_SUPPLY, VESTING_ACCOUNTS},
+    application::{SOMM_DECIMALS, USOMM},
+};
 
 const HOUR_IN_SECS: u64 = 3600;
 pub const DEFAULT_SNAPSHOT_FILE: &str = "sommstats_snapshot.json";
 
-pub fn validate(config: &SommStatsConfig) {
+/// Chain names that would shadow the default chain's `/v1` routes
+const RESERVED_CHAIN_NAMES: &[&str] = &[
+    "balance",
+    "balances",
+    "bonded-ratio",
+    "circulating-supply",
+    "community-pool",
+    "endpoints",
+    "staked-supply",
+    "supply-breakdown",
+    "supply-freshness",
+    "total-supply",
+    "vesting",
+];
+
+/// Environment variable overriding `grpc.endpoints`, as a comma-separated list
+pub const ENDPOINTS_ENV_VAR: &str = "SOMMSTATS_GRPC_ENDPOINTS";
+/// Environment variable overriding `server.port`
+pub const PORT_ENV_VAR: &str = "SOMMSTATS_SERVER_PORT";
+/// Environment variable overriding `server.admin_token`, so the token can be kept out of the
+/// config file
+pub const ADMIN_TOKEN_ENV_VAR: &str = "SOMMSTATS_ADMIN_TOKEN";
+
+/// Applies overrides from environment variables on top of the loaded config. `var` looks up a
+/// variable by name so tests don't have to modify the process environment.
+pub fn apply_env_overrides(
+    mut config: SommStatsConfig,
+    var: impl Fn(&str) -> Option<String>,
+) -> Result<SommStatsConfig, FrameworkError> {
+    if let Some(endpoints) = var(ENDPOINTS_ENV_VAR) {
+        config.grpc.endpoints = endpoints
+            .split(',')
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+            .map(String::from)
+            .collect();
+    }
+    if let Some(port) = var(PORT_ENV_VAR) {
+        config.server.port = port
+            .trim()
+            .parse()
+            .map_err(|_| config_error(format!("invalid {PORT_ENV_VAR} {port:?}")))?;
+    }
+    if let Some(token) = var(ADMIN_TOKEN_ENV_VAR) {
+        config.server.admin_token = Some(token.trim().to_string());
+    }
+
+    Ok(config)
+}
+
+/// Checks the loaded config for values the app can't run with
+pub fn validate(config: &SommStatsConfig) -> Result<(), FrameworkError> {
     if config.grpc.endpoints.is_empty() {
-        panic!("No gRPC endpoints specified in config");
+        return Err(config_error("no gRPC endpoints specified in config"));
+    }
+    for endpoint in config.grpc.endpoints.iter() {
+        if Endpoint::from_shared(endpoint.clone()).is_err() {
+            return Err(config_error(format!("invalid gRPC endpoint {endpoint:?}")));
+        }
     }
     if config.cache.community_pool_update_period == 0
         || config.cache.foundation_wallet_update_period == 0
         || config.cache.vesting_update_period == 0
+        || config.cache.staking_update_period == 0
     {
-        panic!("update periods must be greater than 0");
+        return Err(config_error("update periods must be greater than 0"));
+    }
+    if config.grpc.endpoint_failure_threshold == 0 {
+        return Err(config_error(
+            "endpoint failure threshold must be greater than 0",
+        ));
+    }
+    if config.grpc.base_denom.is_empty() {
+        return Err(config_error("base denom must not be empty"));
+    }
+    // 10^19 is the largest power of 10 that fits in a u64
+    if config.grpc.decimals > 19 {
+        return Err(config_error("decimals must be at most 19"));
+    }
+    // the supply ratios divide by the total supply
+    if config.accounting.total_supply() == 0 {
+        return Err(config_error("total supply override must be greater than 0"));
+    }
+    if config.grpc.max_concurrent_vesting_queries == 0 {
+        return Err(config_error(
+            "max concurrent vesting queries must be greater than 0",
+        ));
+    }
+    if config.grpc.query_timeout_secs == 0 {
+        return Err(config_error("query timeout must be greater than 0"));
+    }
+    if config.server.port == 0 || config.server.port > u16::MAX as u32 {
+        return Err(config_error(format!(
+            "server port {} is out of range",
+            config.server.port
+        )));
+    }
+    if let Some(url) = &config.server.public_base_url {
+        let valid = url
+            .parse::<Uri>()
+            .map(|uri| uri.scheme().is_some() && uri.authority().is_some())
+            .unwrap_or(false);
+        if !valid {
+            return Err(config_error(format!(
+                "invalid server.public_base_url {url:?}, expected an absolute URL"
+            )));
+        }
+    }
+    if config.server.admin_token.as_deref() == Some("") {
+        return Err(config_error("server.admin_token must not be empty"));
+    }
+    if let Err(e) = config.server.socket_addr() {
+        return Err(config_error(format!(
+            "invalid server.address {:?}: {}",
+            config.server.address, e
+        )));
+    }
+
+    for (name, chain) in config.chains.iter() {
+        validate_chain(config, name, chain)?;
+    }
+
+    let accounts = config.accounting.foundation_accounts();
+    let vesting = config.accounting.vesting_accounts();
+    if accounts.is_empty() || vesting.is_empty() {
+        return Err(config_error(
+            "foundation and vesting address lists must not be empty",
+        ));
     }
+    for address in accounts
+        .iter()
+        .chain(vesting.iter())
+        .chain(config.accounting.exclude_from_circulating.iter())
+    {
+        if address.parse::<AccountId>().is_err() {
+            return Err(config_error(format!("invalid address {address:?}")));
+        }
+    }
+    if let Some(address) = config
+        .accounting
+        .exclude_from_circulating
+        .iter()
+        .find(|a| !vesting.contains(a))
+    {
+        return Err(config_error(format!(
+            "exclude_from_circulating address {address:?} is not a vesting address"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Checks a `[chains.<name>]` section. The built-in Sommelier accounts and total supply don't
+/// apply to other chains, so they have to be configured explicitly.
+fn validate_chain(
+    config: &SommStatsConfig,
+    name: &str,
+    chain: &ChainSection,
+) -> Result<(), FrameworkError> {
+    let valid_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if !valid_name || RESERVED_CHAIN_NAMES.contains(&name) {
+        return Err(config_error(format!(
+            "invalid chain name {name:?}, expected lowercase letters, digits and dashes not \
+             clashing with a /v1 route"
+        )));
+    }
+    if chain.accounting.foundation_addresses.is_empty()
+        || chain.accounting.vesting_addresses.is_empty()
+    {
+        return Err(config_error(format!(
+            "chains.{name}: foundation and vesting address lists must not be empty"
+        )));
+    }
+    if chain.accounting.total_supply_override.is_none() {
+        return Err(config_error(format!(
+            "chains.{name}: total_supply_override must be set"
+        )));
+    }
+    if let Some(chain) = config.chain(name) {
+        validate(&chain)?;
+    }
+
+    Ok(())
+}
+
+fn config_error(msg: impl Into<String>) -> FrameworkError {
+    FrameworkErrorKind::ConfigError.context(msg.into()).into()
 }
 
 /// SommStats Configuration
@@ -29,6 +223,30 @@ pub struct SommStatsConfig {
     pub grpc: GrpcSection,
     pub server: ServerSection,
     pub cache: CacheSection,
+    pub accounting: AccountingSection,
+    /// Chains served alongside the default one, keyed by the name used in their routes
+    pub chains: BTreeMap<String, ChainSection>,
+    /// Name of the chain this config was derived for with [`SommStatsConfig::chain`], `None` for
+    /// the default chain
+    #[serde(skip)]
+    pub chain: Option<String>,
+}
+
+impl SommStatsConfig {
+    /// The config of a chain under `[chains]`: its own gRPC and accounting sections with the
+    /// shared server and cache sections. Returns `None` if there is no such chain.
+    pub fn chain(&self, name: &str) -> Option<SommStatsConfig> {
+        let chain = self.chains.get(name)?;
+
+        Some(SommStatsConfig {
+            grpc: chain.grpc.clone(),
+            server: self.server.clone(),
+            cache: self.cache.clone(),
+            accounting: chain.accounting.clone(),
+            chains: BTreeMap::new(),
+            chain: Some(name.to_string()),
+        })
+    }
 }
 
 /// Default configuration settings.
@@ -41,6 +259,9 @@ impl Default for SommStatsConfig {
             grpc: GrpcSection::default(),
             server: ServerSection::default(),
             cache: CacheSection::default(),
+            accounting: AccountingSection::default(),
+            chains: BTreeMap::new(),
+            chain: None,
         }
     }
 }
@@ -50,6 +271,39 @@ impl Default for SommStatsConfig {
 pub struct GrpcSection {
     pub endpoints: Vec<String>,
     pub failed_query_retries: u32,
+    /// Per-poller overrides of `failed_query_retries`
+    pub community_pool_retries: Option<u32>,
+    pub foundation_retries: Option<u32>,
+    pub staking_retries: Option<u32>,
+    pub vesting_retries: Option<u32>,
+    /// Vesting accounts queried at once each cycle
+    pub max_concurrent_vesting_queries: usize,
+    /// Consecutive failures after which an endpoint is temporarily skipped
+    pub endpoint_failure_threshold: u32,
+    /// Seconds a degraded endpoint is skipped before being tried again
+    pub endpoint_cooldown_period: u64,
+    /// Seconds to wait for a gRPC query before treating it as failed
+    pub query_timeout_secs: u64,
+    /// Seconds a successful query result is reused for identical queries to the same endpoint, 0
+    /// to disable
+    pub query_cache_ttl_secs: u64,
+    /// Seconds between HTTP/2 keepalive pings on gRPC connections, 0 to disable
+    pub keepalive_interval_secs: u64,
+    /// Seconds to wait for a keepalive ping to be acknowledged before closing the connection
+    pub keepalive_timeout_secs: u64,
+    /// Whether keepalive pings are sent on connections with no queries in flight
+    pub keepalive_while_idle: bool,
+    /// How queries are spread across healthy endpoints
+    pub endpoint_strategy: EndpointStrategy,
+    /// Seconds between summaries of a repeated query error, which is otherwise only logged the
+    /// first time. 0 logs every occurrence.
+    pub error_log_interval_secs: u64,
+    /// Whether startup fails if any endpoint fails the startup self-test, rather than only warning
+    pub strict_startup: bool,
+    /// Base denom balances are queried in, `usomm` on mainnet
+    pub base_denom: String,
+    /// Decimal places between the base denom and the display denom, 6 for usomm to SOMM
+    pub decimals: u32,
 }
 
 impl Default for GrpcSection {
@@ -57,16 +311,91 @@ impl Default for GrpcSection {
         GrpcSection {
             endpoints: Vec::new(),
             failed_query_retries: 3,
+            community_pool_retries: None,
+            foundation_retries: None,
+            staking_retries: None,
+            vesting_retries: None,
+            max_concurrent_vesting_queries: 8,
+            endpoint_failure_threshold: 3,
+            endpoint_cooldown_period: 300,
+            query_timeout_secs: 10,
+            query_cache_ttl_secs: 5,
+            // hyper's defaults
+            keepalive_interval_secs: 0,
+            keepalive_timeout_secs: 20,
+            keepalive_while_idle: false,
+            endpoint_strategy: EndpointStrategy::default(),
+            error_log_interval_secs: 300,
+            strict_startup: false,
+            base_denom: USOMM.to_string(),
+            decimals: SOMM_DECIMALS,
         }
     }
 }
 
+impl GrpcSection {
+    /// Amount of the base denom in one unit of the display denom
+    pub fn display_divisor(&self) -> u64 {
+        10_u64.pow(self.decimals)
+    }
+}
+
+/// How queries are spread across gRPC endpoints
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EndpointStrategy {
+    /// Query every healthy endpoint at once and take the first success
+    #[default]
+    Ordered,
+    /// Query one healthy endpoint at a time, starting from the next endpoint in turn
+    RoundRobin,
+    /// Query one healthy endpoint at a time, starting from a random endpoint
+    Random,
+}
+
 /// SommStats Configuration
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(default, deny_unknown_fields)]
 pub struct ServerSection {
     pub address: String,
     pub port: u32,
+    /// Origins allowed to make cross-origin requests. `*` allows any origin.
+    pub allowed_origins: Vec<String>,
+    /// Whether responses are compressed when the client accepts it
+    pub compression: bool,
+    /// Requests per second each client IP may make to the `/v1` routes. 0 disables limiting.
+    /// Clients are told apart by the peer address, so leave this off behind a reverse proxy.
+    pub rate_limit_per_sec: u32,
+    /// Most addresses a single `/v1/balances` request may look up
+    pub max_bulk_addresses: usize,
+    /// Whether `/debug/caches` is served. Keep this off in production.
+    pub debug_endpoints: bool,
+    /// Seconds a request may take before it is answered with a 408. 0 disables the timeout.
+    pub request_timeout_secs: u64,
+    /// Seconds a client has to send the request headers before the connection is closed. 0
+    /// disables the timeout.
+    pub header_read_timeout_secs: u64,
+    /// Seconds between TCP keepalive probes on idle connections. 0 disables keepalive.
+    pub tcp_keepalive_secs: u64,
+    /// Seconds in-flight requests are given to complete after a shutdown signal
+    pub shutdown_grace_period_secs: u64,
+    /// Externally visible base URL, e.g. `https://stats.example.com` behind a proxy, used for
+    /// absolute links in responses. Links are relative when unset.
+    pub public_base_url: Option<String>,
+    /// Bearer token required by the `/admin` routes, which are only served when it is set
+    pub admin_token: Option<String>,
+    /// Whether `/v1/supply-breakdown` accepts `?height=`. Each uncached height queries every
+    /// balance from chain, so only enable this where archive queries are affordable.
+    pub historical_queries: bool,
+}
+
+impl ServerSection {
+    /// Address the server binds to. Only IP addresses are accepted, IPv6 without brackets.
+    pub fn socket_addr(&self) -> Result<SocketAddr, AddrParseError> {
+        let ip: IpAddr = self.address.parse()?;
+
+        Ok(SocketAddr::new(ip, self.port as u16))
+    }
 }
 
 impl Default for ServerSection {
@@ -74,6 +403,18 @@ impl Default for ServerSection {
         Self {
             address: String::from("0.0.0.0"),
             port: 8080,
+            allowed_origins: vec![String::from("*")],
+            compression: true,
+            rate_limit_per_sec: 0,
+            max_bulk_addresses: 100,
+            debug_endpoints: false,
+            request_timeout_secs: 30,
+            header_read_timeout_secs: 10,
+            tcp_keepalive_secs: 60,
+            shutdown_grace_period_secs: 10,
+            public_base_url: None,
+            admin_token: None,
+            historical_queries: false,
         }
     }
 }
@@ -84,6 +425,28 @@ pub struct CacheSection {
     pub community_pool_update_period: u64,
     pub vesting_update_period: u64,
     pub foundation_wallet_update_period: u64,
+    pub staking_update_period: u64,
+    /// Consecutive cycles a vesting account must report 0 locked before it is no longer queried.
+    /// 0 disables dropping.
+    pub vesting_drop_threshold: u32,
+    /// Where the balances cache snapshot is written and loaded from
+    pub snapshot_file: String,
+    /// Seconds between cache snapshots while running. 0 disables periodic snapshots.
+    pub snapshot_interval_secs: u64,
+    /// Seconds a balance looked up through `/v1/balance/:address` is cached
+    pub balance_lookup_ttl: u64,
+    /// Circulating supply samples kept in memory, one recorded per cache snapshot. 0 disables the
+    /// history.
+    pub supply_history_len: usize,
+    /// Whether the circulating supply keeps being served from the last cached balances, marked
+    /// stale, when they can't be refreshed. If false a 503 is returned instead.
+    pub serve_stale_on_failure: bool,
+    /// Seconds old the oldest balance may be before the circulating supply is answered with a 503
+    /// instead, regardless of `serve_stale_on_failure`. Unset, only staleness applies.
+    pub max_balance_age_secs: Option<u64>,
+    /// Distinct block heights whose `?height=` balances are kept, oldest evicted first. 0 disables
+    /// caching them.
+    pub historical_heights: usize,
 }
 
 impl Default for CacheSection {
@@ -92,6 +455,212 @@ impl Default for CacheSection {
             community_pool_update_period: HOUR_IN_SECS,
             vesting_update_period: HOUR_IN_SECS,
             foundation_wallet_update_period: HOUR_IN_SECS,
+            staking_update_period: HOUR_IN_SECS,
+            vesting_drop_threshold: 3,
+            snapshot_file: DEFAULT_SNAPSHOT_FILE.to_string(),
+            snapshot_interval_secs: 300,
+            balance_lookup_ttl: 60,
+            supply_history_len: 2016,
+            serve_stale_on_failure: true,
+            max_balance_age_secs: None,
+            historical_heights: 16,
         }
     }
 }
+
+/// A chain served under `/v1/<name>/`. Its balances are polled with its own endpoints, denom and
+/// accounts, while the `[server]` and `[cache]` sections are shared with the default chain.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChainSection {
+    pub grpc: GrpcSection,
+    pub accounting: AccountingSection,
+}
+
+/// Addresses whose balances are excluded from the circulating supply. Empty lists fall back to the
+/// built-in Sommelier addresses.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccountingSection {
+    pub foundation_addresses: Vec<String>,
+    pub vesting_addresses: Vec<String>,
+    /// Total supply in usomm to use instead of the built-in figure. Only meant for pinning a
+    /// known-good value during incidents.
+    pub total_supply_override: Option<u64>,
+    /// Vesting addresses whose locked balances are not subtracted from the total supply, e.g.
+    /// because governance already counts them as restricted elsewhere
+    pub exclude_from_circulating: Vec<String>,
+}
+
+impl AccountingSection {
+    /// The total supply in usomm the circulating supply is calculated from
+    pub fn total_supply(&self) -> u64 {
+        self.total_supply_override.unwrap_or(TOTAL_USOMM_SUPPLY)
+    }
+
+    /// The configured foundation addresses, or the built-in ones if none are configured
+    pub fn foundation_accounts(&self) -> Vec<String> {
+        if self.foundation_addresses.is_empty() {
+            vec![
+                FOUNDATION_ADDRESS.to_string(),
+                FOUNDATION_ADDRESS_2.to_string(),
+            ]
+        } else {
+            self.foundation_addresses.clone()
+        }
+    }
+
+    /// The configured vesting addresses, or the built-in ones if none are configured
+    pub fn vesting_accounts(&self) -> Vec<String> {
+        if self.vesting_addresses.is_empty() {
+            VESTING_ACCOUNTS.iter().map(|v| v.to_string()).collect()
+        } else {
+            self.vesting_addresses.clone()
+        }
+    }
+
+    /// The vesting addresses whose locked balances are subtracted from the total supply
+    pub fn subtracted_vesting_accounts(&self) -> Vec<String> {
+        self.vesting_accounts()
+            .into_iter()
+            .filter(|a| !self.exclude_from_circulating.contains(a))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid_config() -> SommStatsConfig {
+        let mut config = SommStatsConfig::default();
+        config.grpc.endpoints = vec!["http://localhost:9090".to_string()];
+        config
+    }
+
+    #[test]
+    fn env_overrides_take_precedence() {
+        let vars = |name: &str| match name {
+            ENDPOINTS_ENV_VAR => Some("http://a:9090, http://b:9090,".to_string()),
+            PORT_ENV_VAR => Some("9000".to_string()),
+            ADMIN_TOKEN_ENV_VAR => Some("secret\n".to_string()),
+            _ => None,
+        };
+        let config = apply_env_overrides(valid_config(), vars).unwrap();
+        assert_eq!(Some("secret"), config.server.admin_token.as_deref());
+
+        assert_eq!(
+            vec!["http://a:9090", "http://b:9090"],
+            config.grpc.endpoints
+        );
+        assert_eq!(9000, config.server.port);
+
+        let config = apply_env_overrides(valid_config(), |_| None).unwrap();
+        assert_eq!(vec!["http://localhost:9090"], config.grpc.endpoints);
+        assert_eq!(8080, config.server.port);
+
+        let invalid = |name: &str| (name == PORT_ENV_VAR).then(|| "http".to_string());
+        assert!(apply_env_overrides(valid_config(), invalid).is_err());
+    }
+
+    #[test]
+    fn validate_accepts_defaults_with_endpoint() {
+        assert!(validate(&valid_config()).is_ok());
+        assert!(validate(&SommStatsConfig::default()).is_err());
+    }
+
+    #[test]
+    fn validate_rejects_bad_values() {
+        let mut config = valid_config();
+        config.grpc.endpoints = vec!["not a url".to_string()];
+        assert!(validate(&config).is_err());
+
+        let mut config = valid_config();
+        config.server.port = 70000;
+        assert!(validate(&config).is_err());
+
+        let mut config = valid_config();
+        config.server.public_base_url = Some("/relative".to_string());
+        assert!(validate(&config).is_err());
+
+        let mut config = valid_config();
+        config.server.admin_token = Some(String::new());
+        assert!(validate(&config).is_err());
+
+        let mut config = valid_config();
+        config.server.address = "foo".to_string();
+        let err = validate(&config).unwrap_err().to_string();
+        assert!(err.contains("invalid server.address \"foo\""), "{err}");
+
+        let mut config = valid_config();
+        config.cache.vesting_update_period = 0;
+        assert!(validate(&config).is_err());
+
+        let mut config = valid_config();
+        config.accounting.foundation_addresses = vec!["somm1notanaddress".to_string()];
+        assert!(validate(&config).is_err());
+
+        let mut config = valid_config();
+        config.accounting.total_supply_override = Some(0);
+        assert!(validate(&config).is_err());
+
+        let mut config = valid_config();
+        config.accounting.exclude_from_circulating = vec![FOUNDATION_ADDRESS.to_string()];
+        let err = validate(&config).unwrap_err().to_string();
+        assert!(err.contains("is not a vesting address"), "{err}");
+    }
+
+    fn chain_section() -> ChainSection {
+        let mut chain = ChainSection::default();
+        chain.grpc.endpoints = vec!["http://localhost:9091".to_string()];
+        chain.grpc.base_denom = "uatom".to_string();
+        chain.accounting.foundation_addresses =
+            vec!["cosmos1ymy6sx49d538gtdw2y6jnqwhcv3v9de85e9034".to_string()];
+        chain.accounting.vesting_addresses =
+            vec!["cosmos1x52ufr8f6mheac4z43w0tl0e89lzxryup93r6s".to_string()];
+        chain.accounting.total_supply_override = Some(1_000_000);
+        chain
+    }
+
+    #[test]
+    fn chain_shares_server_and_cache_sections() {
+        let mut config = valid_config();
+        config.server.port = 9000;
+        config.cache.vesting_update_period = 60;
+        config.chains.insert("hub".to_string(), chain_section());
+
+        let chain = config.chain("hub").unwrap();
+        assert_eq!(Some("hub"), chain.chain.as_deref());
+        assert_eq!("uatom", chain.grpc.base_denom);
+        assert_eq!(Some(1_000_000), chain.accounting.total_supply_override);
+        assert_eq!(9000, chain.server.port);
+        assert_eq!(60, chain.cache.vesting_update_period);
+        assert!(chain.chains.is_empty());
+        assert!(config.chain("osmosis").is_none());
+        assert!(validate(&config).is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_bad_chains() {
+        let with_chain = |name: &str, chain: ChainSection| {
+            let mut config = valid_config();
+            config.chains.insert(name.to_string(), chain);
+            validate(&config)
+        };
+
+        assert!(with_chain("Hub", chain_section()).is_err());
+        assert!(with_chain("vesting", chain_section()).is_err());
+
+        let mut chain = chain_section();
+        chain.accounting.total_supply_override = None;
+        assert!(with_chain("hub", chain).is_err());
+
+        let mut chain = chain_section();
+        chain.accounting.vesting_addresses.clear();
+        assert!(with_chain("hub", chain).is_err());
+
+        let mut chain = chain_section();
+        chain.grpc.endpoints.clear();
+        assert!(with_chain("hub", chain).is_err());
+    }
+}
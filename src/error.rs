@@ -1,6 +1,10 @@
 //! Error types
 
 use abscissa_core::error::{BoxError, Context};
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
 use std::{
     fmt::{self, Display},
     io,
@@ -8,6 +12,11 @@ use std::{
 };
 use thiserror::Error;
 
+use crate::{
+    application::ACCOUNT_PREFIX,
+    server::{self, SupplyError},
+};
+
 /// Kinds of errors
 #[derive(Copy, Clone, Debug, Eq, Error, PartialEq)]
 pub enum ErrorKind {
@@ -68,3 +77,202 @@ impl From<io::Error> for Error {
         ErrorKind::Io.context(err).into()
     }
 }
+
+/// Error code for a 503 because a balance needed by the response hasn't been cached yet
+pub const CACHE_INCOMPLETE: &str = "cache_incomplete";
+/// Error code for a 503 because a cached balance is stale and serving stale values is disabled
+pub const CACHE_STALE: &str = "cache_stale";
+/// Error code for a 500 because the cached balances sum to more than the total supply
+pub const SUPPLY_EXCEEDS_TOTAL: &str = "supply_exceeds_total";
+/// Error code for a 400 because the requested address isn't a valid Sommelier address
+pub const INVALID_ADDRESS: &str = "invalid_address";
+/// Error code for a 400 because a request parameter couldn't be parsed
+pub const INVALID_REQUEST: &str = "invalid_request";
+/// Error code for a 404 because the requested resource doesn't exist
+pub const NOT_FOUND: &str = "not_found";
+/// Error code for a 401 because the request lacks a valid admin token
+pub const UNAUTHORIZED: &str = "unauthorized";
+/// Error code for a 503 because there are no gRPC endpoints to query
+pub const NO_GRPC_ENDPOINTS: &str = "no_grpc_endpoints";
+/// Error code for a 503 because every gRPC endpoint failed the query
+pub const QUERY_FAILED: &str = "query_failed";
+/// Error code for a 429 because the client exceeded its rate limit
+pub const RATE_LIMITED: &str = "rate_limited";
+/// Error code for a 503 because metrics can't be rendered
+pub const METRICS_UNAVAILABLE: &str = "metrics_unavailable";
+/// Error code for a 500 because the response couldn't be built
+pub const INTERNAL_ERROR: &str = "internal_error";
+
+/// Errors returned by the API handlers. Each variant maps to an HTTP status and one of the stable
+/// error codes in the JSON error body.
+#[derive(Debug, Error)]
+pub enum SommStatsError {
+    /// A balance needed for the response hasn't been cached yet
+    #[error("{0} is not cached yet")]
+    CacheMiss(String),
+
+    /// A cached balance is older than serving stale values allows
+    #[error("{0} is stale")]
+    CacheStale(String),
+
+    /// The cached balances sum to more than the total supply
+    #[error("cached balances exceed the total supply")]
+    SupplyExceedsTotal,
+
+    /// The requested address isn't a valid Sommelier address
+    #[error("invalid {prefix} address {0:?}", prefix = ACCOUNT_PREFIX)]
+    InvalidAddress(String),
+
+    /// Client input couldn't be parsed
+    #[error("{0}")]
+    ParseError(String),
+
+    /// The requested resource doesn't exist
+    #[error("{0}")]
+    NotFound(String),
+
+    /// The request lacks a valid admin token
+    #[error("missing or invalid admin token")]
+    Unauthorized,
+
+    /// There are no gRPC endpoints to query
+    #[error("no gRPC endpoints are configured")]
+    NoGrpcEndpoints,
+
+    /// Every gRPC endpoint failed the query
+    #[error("{0}")]
+    GrpcUnavailable(String),
+
+    /// Metrics can't be rendered because no recorder is installed
+    #[error("metrics recorder is not installed")]
+    MetricsUnavailable,
+
+    /// The response couldn't be built
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl SommStatsError {
+    /// HTTP status the error is reported with
+    pub fn status(&self) -> StatusCode {
+        match self {
+            SommStatsError::InvalidAddress(_) | SommStatsError::ParseError(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            SommStatsError::NotFound(_) => StatusCode::NOT_FOUND,
+            SommStatsError::Unauthorized => StatusCode::UNAUTHORIZED,
+            SommStatsError::SupplyExceedsTotal | SommStatsError::Internal(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            SommStatsError::CacheMiss(_)
+            | SommStatsError::CacheStale(_)
+            | SommStatsError::NoGrpcEndpoints
+            | SommStatsError::GrpcUnavailable(_)
+            | SommStatsError::MetricsUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    /// Machine-readable code reported in the error body
+    pub fn code(&self) -> &'static str {
+        match self {
+            SommStatsError::CacheMiss(_) => CACHE_INCOMPLETE,
+            SommStatsError::CacheStale(_) => CACHE_STALE,
+            SommStatsError::SupplyExceedsTotal => SUPPLY_EXCEEDS_TOTAL,
+            SommStatsError::InvalidAddress(_) => INVALID_ADDRESS,
+            SommStatsError::ParseError(_) => INVALID_REQUEST,
+            SommStatsError::NotFound(_) => NOT_FOUND,
+            SommStatsError::Unauthorized => UNAUTHORIZED,
+            SommStatsError::NoGrpcEndpoints => NO_GRPC_ENDPOINTS,
+            SommStatsError::GrpcUnavailable(_) => QUERY_FAILED,
+            SommStatsError::MetricsUnavailable => METRICS_UNAVAILABLE,
+            SommStatsError::Internal(_) => INTERNAL_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for SommStatsError {
+    fn into_response(self) -> Response {
+        server::error_response(self.status(), self.code(), self.to_string())
+    }
+}
+
+impl From<SupplyError> for SommStatsError {
+    fn from(err: SupplyError) -> Self {
+        match err {
+            SupplyError::MissingBalance(key) => SommStatsError::CacheMiss(format!("balance {key}")),
+            SupplyError::ExceedsTotal(_) => SommStatsError::SupplyExceedsTotal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_map_to_status_and_code() {
+        let cases = [
+            (
+                SommStatsError::CacheMiss("balance foo".to_string()),
+                StatusCode::SERVICE_UNAVAILABLE,
+                CACHE_INCOMPLETE,
+            ),
+            (
+                SommStatsError::CacheStale("balance foo".to_string()),
+                StatusCode::SERVICE_UNAVAILABLE,
+                CACHE_STALE,
+            ),
+            (
+                SommStatsError::SupplyExceedsTotal,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                SUPPLY_EXCEEDS_TOTAL,
+            ),
+            (
+                SommStatsError::InvalidAddress("foo".to_string()),
+                StatusCode::BAD_REQUEST,
+                INVALID_ADDRESS,
+            ),
+            (
+                SommStatsError::ParseError("foo".to_string()),
+                StatusCode::BAD_REQUEST,
+                INVALID_REQUEST,
+            ),
+            (
+                SommStatsError::NotFound("foo".to_string()),
+                StatusCode::NOT_FOUND,
+                NOT_FOUND,
+            ),
+            (
+                SommStatsError::Unauthorized,
+                StatusCode::UNAUTHORIZED,
+                UNAUTHORIZED,
+            ),
+            (
+                SommStatsError::NoGrpcEndpoints,
+                StatusCode::SERVICE_UNAVAILABLE,
+                NO_GRPC_ENDPOINTS,
+            ),
+            (
+                SommStatsError::GrpcUnavailable("foo".to_string()),
+                StatusCode::SERVICE_UNAVAILABLE,
+                QUERY_FAILED,
+            ),
+            (
+                SommStatsError::MetricsUnavailable,
+                StatusCode::SERVICE_UNAVAILABLE,
+                METRICS_UNAVAILABLE,
+            ),
+            (
+                SommStatsError::Internal("foo".to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                INTERNAL_ERROR,
+            ),
+        ];
+
+        for (err, status, code) in cases {
+            assert_eq!(status, err.status(), "{err:?}");
+            assert_eq!(code, err.code(), "{err:?}");
+            assert_eq!(status, err.into_response().status());
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Per-endpoint health tracking
+//!
+//! Endpoints that fail repeatedly are temporarily skipped by the pollers so that a dead node
+//! doesn't eat into the retry budget on every cycle. Once the cooldown has elapsed the endpoint
+//! is tried again, and a single success marks it healthy.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::config::GrpcSection;
+
+/// Tracks consecutive failures for each gRPC endpoint
+#[derive(Debug)]
+pub struct EndpointHealth {
+    failure_threshold: u32,
+    cooldown: Duration,
+    statuses: HashMap<String, EndpointStatus>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct EndpointStatus {
+    consecutive_failures: u32,
+    skipped_until: Option<Instant>,
+}
+
+/// Health of a single endpoint as reported by the API
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct EndpointHealthReport {
+    pub endpoint: String,
+    pub healthy: bool,
+    pub consecutive_failures: u32,
+    /// Seconds until a degraded endpoint is queried again
+    pub retry_in_secs: Option<u64>,
+}
+
+impl Default for EndpointHealth {
+    fn default() -> Self {
+        let grpc = GrpcSection::default();
+
+        Self::new(
+            grpc.endpoint_failure_threshold,
+            grpc.endpoint_cooldown_period,
+        )
+    }
+}
+
+impl EndpointHealth {
+    pub fn new(failure_threshold: u32, cooldown_secs: u64) -> Self {
+        Self {
+            failure_threshold,
+            cooldown: Duration::from_secs(cooldown_secs),
+            statuses: HashMap::new(),
+        }
+    }
+
+    /// Applies the threshold and cooldown from the gRPC config
+    pub fn configure(&mut self, config: &GrpcSection) {
+        self.failure_threshold = config.endpoint_failure_threshold;
+        self.cooldown = Duration::from_secs(config.endpoint_cooldown_period);
+    }
+
+    pub fn record_success(&mut self, endpoint: &str) {
+        self.statuses.remove(endpoint);
+    }
+
+    pub fn record_failure(&mut self, endpoint: &str) {
+        let status = self.statuses.entry(endpoint.to_string()).or_default();
+        status.consecutive_failures += 1;
+
+        if status.consecutive_failures >= self.failure_threshold {
+            status.skipped_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+
+    /// Whether the endpoint should currently be queried
+    pub fn is_available(&self, endpoint: &str) -> bool {
+        match self.statuses.get(endpoint).and_then(|s| s.skipped_until) {
+            Some(until) => Instant::now() >= until,
+            None => true,
+        }
+    }
+
+    /// Filters out endpoints that are cooling down. If every endpoint is degraded, all of them are
+    /// returned so that queries are still attempted.
+    pub fn available<'a>(&self, endpoints: &'a [String]) -> Vec<&'a String> {
+        let available: Vec<&String> = endpoints.iter().filter(|e| self.is_available(e)).collect();
+
+        if available.is_empty() {
+            endpoints.iter().collect()
+        } else {
+            available
+        }
+    }
+
+    pub fn report(&self, endpoints: &[String]) -> Vec<EndpointHealthReport> {
+        let now = Instant::now();
+
+        endpoints
+            .iter()
+            .map(|e| {
+                let status = self.statuses.get(e).cloned().unwrap_or_default();
+                let retry_in_secs = status
+                    .skipped_until
+                    .filter(|until| *until > now)
+                    .map(|until| (until - now).as_secs());
+
+                EndpointHealthReport {
+                    endpoint: e.clone(),
+                    healthy: retry_in_secs.is_none(),
+                    consecutive_failures: status.consecutive_failures,
+                    retry_in_secs,
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,99 @@
+//! Circulating supply history
+//!
+//! Each time the cache snapshot is taken the current circulating supply is appended to an
+//! in-memory ring buffer, so it can be charted over time. Only the most recent
+//! `supply_history_len` samples are kept, and the history starts over on restart.
+
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    application::{BALANCES, SUPPLY_HISTORY},
+    config::SommStatsConfig,
+    server::calculate_circulating_supply,
+};
+
+/// Circulating supply at a point in time
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize, ToSchema)]
+pub struct SupplySample {
+    /// Unix timestamp the sample was taken at
+    pub timestamp: u64,
+    pub circulating_supply_usomm: u64,
+}
+
+/// Appends the current circulating supply to the history, dropping the oldest sample once the
+/// history is full. Nothing is recorded while the supply can't be calculated.
+pub async fn record_supply_sample(config: &SommStatsConfig, now: SystemTime) {
+    let capacity = config.cache.supply_history_len;
+    if capacity == 0 {
+        return;
+    }
+    let Ok(circulating_supply_usomm) =
+        calculate_circulating_supply(config, &*BALANCES.read().await)
+    else {
+        return;
+    };
+
+    let timestamp = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let mut history = SUPPLY_HISTORY.write().await;
+    history.push_back(SupplySample {
+        timestamp,
+        circulating_supply_usomm,
+    });
+    while history.len() > capacity {
+        history.pop_front();
+    }
+}
+
+/// Returns the recorded samples taken between `from` and `to` inclusive, oldest first
+pub async fn supply_history(from: Option<u64>, to: Option<u64>) -> Vec<SupplySample> {
+    SUPPLY_HISTORY
+        .read()
+        .await
+        .iter()
+        .filter(|s| from.is_none_or(|from| s.timestamp >= from))
+        .filter(|s| to.is_none_or(|to| s.timestamp <= to))
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        application::ChainState,
+        query::{update_balance, COMMUNITY_POOL_KEY},
+    };
+    use assay::assay;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[assay]
+    async fn history_is_capped_and_filtered() {
+        let mut config = SommStatsConfig::default();
+        config.accounting.foundation_addresses = vec!["somm1foundation".to_string()];
+        config.accounting.vesting_addresses = vec!["somm1vesting".to_string()];
+        config.cache.supply_history_len = 3;
+
+        // not recorded until the supply can be calculated
+        record_supply_sample(&config, UNIX_EPOCH).await;
+        assert!(supply_history(None, None).await.is_empty());
+
+        update_balance(&ChainState::get(None), "somm1foundation", 1).await;
+        update_balance(&ChainState::get(None), "somm1vesting", 1).await;
+        update_balance(&ChainState::get(None), COMMUNITY_POOL_KEY, 1).await;
+        for secs in 1..=4 {
+            record_supply_sample(&config, UNIX_EPOCH + Duration::from_secs(secs)).await;
+        }
+
+        let timestamps =
+            |samples: Vec<SupplySample>| samples.iter().map(|s| s.timestamp).collect::<Vec<u64>>();
+        assert_eq!(vec![2, 3, 4], timestamps(supply_history(None, None).await));
+        assert_eq!(vec![3, 4], timestamps(supply_history(Some(3), None).await));
+        assert_eq!(vec![2, 3], timestamps(supply_history(None, Some(3)).await));
+    }
+}
@@ -17,9 +17,20 @@
 
 pub mod accounting;
 pub mod application;
+pub mod client;
 pub mod commands;
 pub mod config;
 pub mod error;
+pub mod health;
+pub mod history;
+pub mod log_dedup;
+pub mod openapi;
 pub mod prelude;
 pub mod query;
+pub mod rate_limit;
+pub mod reload;
+pub mod selector;
 pub mod server;
+pub mod snapshot;
+pub mod source;
+pub mod telemetry;
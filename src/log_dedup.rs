@@ -0,0 +1,147 @@
+//! Deduplicated error logging
+//!
+//! While an endpoint is down the pollers fail with the same error on every retry of every cycle.
+//! The first occurrence of a message is logged, identical repeats are counted instead, and a
+//! summary with the count is logged once per interval while the failures continue.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::config::GrpcSection;
+
+#[derive(Debug)]
+pub struct LogDeduper {
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    interval: Duration,
+    messages: HashMap<String, Occurrences>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Occurrences {
+    last_logged: Instant,
+    last_seen: Instant,
+    suppressed: u64,
+}
+
+impl Default for LogDeduper {
+    fn default() -> Self {
+        Self::new(GrpcSection::default().error_log_interval_secs)
+    }
+}
+
+impl LogDeduper {
+    pub fn new(interval_secs: u64) -> Self {
+        Self {
+            state: Mutex::new(State {
+                interval: Duration::from_secs(interval_secs),
+                messages: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Applies the summary interval from the gRPC config
+    pub fn configure(&self, config: &GrpcSection) {
+        self.state.lock().unwrap().interval = Duration::from_secs(config.error_log_interval_secs);
+    }
+
+    /// Returns the line to log for `message`: the message itself the first time it is seen, a
+    /// summary of the repeats once the interval has passed since it was last logged, or `None` if
+    /// it should be suppressed. A message that hasn't recurred within the interval is forgotten.
+    /// An interval of 0 disables deduplication.
+    pub fn check(&self, message: String, now: Instant) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        let interval = state.interval;
+        if interval.is_zero() {
+            return Some(message);
+        }
+
+        state
+            .messages
+            .retain(|_, o| now.saturating_duration_since(o.last_seen) < interval);
+        let Some(occurrences) = state.messages.get_mut(&message) else {
+            state.messages.insert(
+                message.clone(),
+                Occurrences {
+                    last_logged: now,
+                    last_seen: now,
+                    suppressed: 0,
+                },
+            );
+            return Some(message);
+        };
+
+        occurrences.last_seen = now;
+        let since_logged = now.saturating_duration_since(occurrences.last_logged);
+        if since_logged < interval {
+            occurrences.suppressed += 1;
+            return None;
+        }
+
+        let count = occurrences.suppressed + 1;
+        occurrences.last_logged = now;
+        occurrences.suppressed = 0;
+
+        Some(format!(
+            "still failing, {} occurrences in the last {} seconds: {}",
+            count,
+            since_logged.as_secs(),
+            message
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suppresses_repeats_and_summarizes() {
+        let deduper = LogDeduper::new(60);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(
+            Some("down".to_string()),
+            deduper.check("down".to_string(), at(0))
+        );
+        assert_eq!(None, deduper.check("down".to_string(), at(10)));
+        assert_eq!(None, deduper.check("down".to_string(), at(50)));
+        assert_eq!(
+            Some("other".to_string()),
+            deduper.check("other".to_string(), at(55))
+        );
+        assert_eq!(
+            Some("still failing, 3 occurrences in the last 100 seconds: down".to_string()),
+            deduper.check("down".to_string(), at(100))
+        );
+        assert_eq!(None, deduper.check("down".to_string(), at(110)));
+    }
+
+    #[test]
+    fn forgets_messages_that_stop_recurring() {
+        let deduper = LogDeduper::new(60);
+        let start = Instant::now();
+
+        deduper.check("down".to_string(), start);
+        assert_eq!(
+            Some("down".to_string()),
+            deduper.check("down".to_string(), start + Duration::from_secs(61))
+        );
+    }
+
+    #[test]
+    fn zero_interval_logs_everything() {
+        let deduper = LogDeduper::new(0);
+        let now = Instant::now();
+
+        assert!(deduper.check("down".to_string(), now).is_some());
+        assert!(deduper.check("down".to_string(), now).is_some());
+    }
+}
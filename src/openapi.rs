@@ -0,0 +1,62 @@
+//! OpenAPI document
+//!
+//! The document is generated from the `#[utoipa::path]` annotations on the handlers in
+//! `server.rs` and the `ToSchema` derives on their response types, so it stays in sync with the
+//! routes.
+
+use utoipa::OpenApi;
+
+use crate::{
+    health::EndpointHealthReport,
+    history::SupplySample,
+    server::{
+        self, AccountBalanceResponse, BondedRatioResponse, BulkBalancesResponse,
+        CirculatingSupplyResponse, CommunityPoolResponse, ErrorBody, ErrorResponse,
+        HealthzResponse, ReadinessResponse, StakedSupplyResponse, SupplyBreakdownResponse,
+        TotalSupplyResponse, VersionResponse, VestingAccountBalance, VestingResponse,
+    },
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "sommstats", description = "Sommelier supply statistics"),
+    paths(
+        server::get_circulating_supply,
+        server::get_circulating_supply_history,
+        server::get_total_supply,
+        server::get_staked_supply,
+        server::get_bonded_ratio,
+        server::get_community_pool,
+        server::get_supply_breakdown,
+        server::get_vesting,
+        server::get_supply_freshness,
+        server::get_account_balance,
+        server::post_balances,
+        server::get_endpoints_health,
+        server::get_readiness,
+        server::get_healthz,
+        server::get_version,
+        server::get_metrics,
+        server::get_openapi,
+    ),
+    components(schemas(
+        AccountBalanceResponse,
+        BondedRatioResponse,
+        BulkBalancesResponse,
+        CirculatingSupplyResponse,
+        CommunityPoolResponse,
+        EndpointHealthReport,
+        ErrorBody,
+        ErrorResponse,
+        HealthzResponse,
+        ReadinessResponse,
+        StakedSupplyResponse,
+        SupplyBreakdownResponse,
+        SupplySample,
+        TotalSupplyResponse,
+        VestingAccountBalance,
+        VersionResponse,
+        VestingResponse,
+    ))
+)]
+pub struct ApiDoc;
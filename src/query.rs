@@ -1,24 +1,38 @@
-use abscissa_core::{
-    tracing::{
-        debug,
-        log::{error, info, warn},
-    },
-    Application,
+use std::{
+    collections::HashMap,
+    future::Future,
+    time::{Duration, Instant, SystemTime},
+};
+
+use abscissa_core::tracing::{
+    debug,
+    log::{info, log, warn, Level},
 };
 use abscissa_tokio::tokio;
 use chrono::Utc;
-use eyre::{bail, Result};
-use ocular::{
-    cosmrs::proto::{
-        cosmos::{
-            base::v1beta1::{Coin, DecCoin},
-            vesting::v1beta1::{
-                ContinuousVestingAccount, DelayedVestingAccount, PeriodicVestingAccount,
-            },
+use eyre::{bail, eyre, Result, WrapErr};
+use futures::{
+    future::{join_all, select_ok, BoxFuture},
+    stream, FutureExt, StreamExt,
+};
+use metrics::{gauge, histogram, increment_counter};
+use ocular::cosmrs::proto::{
+    cosmos::{
+        auth::v1beta1::QueryAccountRequest,
+        bank::v1beta1::QueryBalanceRequest,
+        base::{
+            tendermint::v1beta1::{GetBlockByHeightRequest, GetLatestBlockRequest},
+            v1beta1::{Coin, DecCoin},
+        },
+        distribution::v1beta1::QueryCommunityPoolRequest,
+        staking::v1beta1::QueryPoolRequest,
+        vesting::v1beta1::{
+            BaseVestingAccount, ContinuousVestingAccount, DelayedVestingAccount,
+            PeriodicVestingAccount, PermanentLockedAccount,
         },
-        traits::Message,
     },
-    QueryClient,
+    traits::Message,
+    Any,
 };
 use tokio_retry::{
     strategy::{jitter, ExponentialBackoff},
@@ -26,35 +40,63 @@ use tokio_retry::{
 };
 
 use crate::{
-    accounting::{FOUNDATION_ADDRESS, FOUNDATION_ADDRESS_2, VESTING_ACCOUNTS},
-    application::{BALANCES, USOMM},
-    prelude::APP,
+    application::{
+        ChainState, ENDPOINT_HEALTH, ENDPOINT_PROBE, ENDPOINT_SELECTOR, LOG_DEDUPER,
+        LOOKUP_BALANCES, QUERY_RESULTS,
+    },
+    client::{
+        at_height, auth_client, bank_client, distribution_client, evict_on_transport_error,
+        response_height, staking_client, tendermint_client,
+    },
+    config::{EndpointStrategy, GrpcSection, SommStatsConfig},
+    reload::{current_chain_config, sleep_until_reload, subscribe_reloads},
+    source::{BalanceSource, Observed, PastBlock},
+    telemetry::{
+        BALANCE_LAST_UPDATED, CACHE_EXPIRED_TOTAL, QUERY_FAILURE_TOTAL, QUERY_SUCCESS_TOTAL,
+        UPDATE_DURATION_SECONDS,
+    },
 };
 
-const _BASE_VESTING_ACCOUNT_TYPE_URL: &str = "/cosmos.vesting.v1beta1.BaseVestingAccount";
+const BASE_VESTING_ACCOUNT_TYPE_URL: &str = "/cosmos.vesting.v1beta1.BaseVestingAccount";
 const CONTINUOUS_VESTING_ACCOUNT_TYPE_URL: &str =
     "/cosmos.vesting.v1beta1.ContinuousVestingAccount";
 const PERIODIC_VESTING_ACCOUNT_TYPE_URL: &str = "/cosmos.vesting.v1beta1.PeriodicVestingAccount";
 const DELAYED_VESTING_ACCOUNT_TYPE_URL: &str = "/cosmos.vesting.v1beta1.DelayedVestingAccount";
+const PERMANENT_LOCKED_ACCOUNT_TYPE_URL: &str = "/cosmos.vesting.v1beta1.PermanentLockedAccount";
+
+/// Number of decimal places in an SDK `Dec`
+const SDK_DEC_PRECISION: usize = 18;
 
 pub const COMMUNITY_POOL_KEY: &str = "communitypool";
+pub const BONDED_KEY: &str = "bonded";
 
-/// Updates the cached total usomm balance of the foundation wallet
-pub async fn update_foundation_balance(endpoint: &str) -> Result<()> {
-    match QueryClient::new(endpoint)?
-        .balance(FOUNDATION_ADDRESS, USOMM)
+/// Queries the usomm balance of an address at `height`, or at the latest height if `None`
+pub async fn query_balance(
+    endpoint: &str,
+    address: &str,
+    denom: &str,
+    height: Option<u64>,
+) -> Result<Observed> {
+    let request = QueryBalanceRequest {
+        address: address.to_string(),
+        denom: denom.to_string(),
+    };
+    match bank_client(endpoint)
+        .await?
+        .balance(at_height(request, height))
         .await
     {
-        Ok(b) => {
-            let balance = b.balance.unwrap().amount as u64;
-            update_balance(FOUNDATION_ADDRESS, balance).await;
-            info!("foundation wallet balance updated: {}usomm", balance);
+        Ok(r) => {
+            let height = response_height(r.metadata());
+            let amount = get_usomm_amount(r.into_inner().balance.into_iter().collect(), denom)?;
 
-            Ok(())
+            Ok(Observed { amount, height })
         }
         Err(e) => {
+            evict_on_transport_error(endpoint, &e).await;
             bail!(
-                "error querying foundation wallet balance from endpoint {}: {:?}",
+                "error querying balance of {} from endpoint {}: {:?}",
+                address,
                 endpoint,
                 e
             );
@@ -62,22 +104,155 @@ pub async fn update_foundation_balance(endpoint: &str) -> Result<()> {
     }
 }
 
-/// Updates the cached total usomm balance of the foundation wallet
-pub async fn update_foundation_balance_2(endpoint: &str) -> Result<()> {
-    match QueryClient::new(endpoint)?
-        .balance(FOUNDATION_ADDRESS_2, USOMM)
+/// Queries the usomm balance of an address at `height` from the first endpoint to respond
+pub async fn fetch_balance(
+    source: &dyn BalanceSource,
+    grpc: &GrpcSection,
+    address: &str,
+    height: Option<u64>,
+) -> Result<Observed> {
+    let method = method_at_height("bank/Balance", height);
+    race_endpoints(&grpc.endpoints, query_timeout(grpc), |e| {
+        let query = source.balance(e, address, &grpc.base_denom, height);
+        memoized(grpc, e, &method, &[address, &grpc.base_denom], query).boxed()
+    })
+    .await
+}
+
+/// Updates the cached total usomm balance of a foundation wallet of the chain `config` is for
+pub async fn update_foundation_balance(
+    source: &dyn BalanceSource,
+    config: &SommStatsConfig,
+    address: &str,
+) -> Result<()> {
+    let Observed {
+        amount: balance,
+        height,
+    } = timed(
+        "foundation",
+        fetch_balance(source, &config.grpc, address, None),
+    )
+    .await
+    .wrap_err_with(|| {
+        format!("failed to query foundation wallet {address} balance from all endpoints")
+    })?;
+    let state = ChainState::of(config);
+    update_balance(&state, address, balance).await;
+    record_height(&state, address, height).await;
+    info!(
+        "foundation wallet {} balance updated: {}usomm",
+        address, balance
+    );
+
+    Ok(())
+}
+
+/// Runs every balance poller of `chain`, or of the default chain for `None`
+pub async fn poll_balances(source: &dyn BalanceSource, chain: Option<&str>) {
+    let _ = tokio::join!(
+        poll_vesting_balance(source, chain),
+        poll_foundation_balance(source, chain),
+        poll_community_pool_balance(source, chain),
+        poll_staking_balance(source, chain),
+    );
+}
+
+/// Periodically updates the cached foundation balances of `chain`. The config is re-read every
+/// cycle, and a reload starts a new cycle straight away.
+pub async fn poll_foundation_balance(
+    source: &dyn BalanceSource,
+    chain: Option<&str>,
+) -> Result<()> {
+    debug!(
+        "updating foundation wallet balance every {} seconds",
+        current_chain_config(chain)
+            .cache
+            .foundation_wallet_update_period
+    );
+
+    let mut reloads = subscribe_reloads();
+    loop {
+        reloads.borrow_and_update();
+        let config = current_chain_config(chain);
+        let retry_strategy = retry_strategy(
+            config
+                .grpc
+                .foundation_retries
+                .unwrap_or(config.grpc.failed_query_retries),
+        );
+        debug!("updating foundation wallet balances");
+        for address in config.accounting.foundation_accounts().iter() {
+            Retry::spawn(retry_strategy.clone(), || async {
+                update_foundation_balance(source, &config, address).await
+            })
+            .await
+            .unwrap_or_else(|e| log_deduped(Level::Error, format!("{e:?}")));
+        }
+        sleep_until_reload(
+            Duration::from_secs(config.cache.foundation_wallet_update_period),
+            &mut reloads,
+        )
+        .await;
+    }
+}
+
+/// Jittered retry with exponential backoff
+fn retry_strategy(retries: u32) -> impl Iterator<Item = Duration> + Clone {
+    ExponentialBackoff::from_millis(500)
+        .map(jitter)
+        .take(retries as usize)
+}
+
+/// Returns the balance of an arbitrary address, querying it only if the cached value is older than
+/// `ttl`
+pub async fn lookup_balance(
+    source: &dyn BalanceSource,
+    grpc: &GrpcSection,
+    ttl: Duration,
+    address: &str,
+) -> Result<u64> {
+    if let Some((balance, queried_at)) = LOOKUP_BALANCES.read().await.get(address) {
+        if queried_at.elapsed() < ttl {
+            return Ok(*balance);
+        }
+        increment_counter!(CACHE_EXPIRED_TOTAL, "cache" => "balance_lookup");
+    }
+
+    let balance = fetch_balance(source, grpc, address, None)
+        .await
+        .wrap_err_with(|| format!("failed to query balance of {address} from all endpoints"))?
+        .amount;
+
+    let mut balances = LOOKUP_BALANCES.write().await;
+    // drop expired entries so arbitrary lookups can't grow the cache without bound
+    balances.retain(|_, (_, queried_at)| queried_at.elapsed() < ttl);
+    balances.insert(address.to_string(), (balance, Instant::now()));
+
+    Ok(balance)
+}
+
+/// Queries the total usomm balance in the community pool at `height`, or at the latest height if
+/// `None`
+pub async fn query_community_pool_balance(
+    endpoint: &str,
+    denom: &str,
+    height: Option<u64>,
+) -> Result<Observed> {
+    match distribution_client(endpoint)
+        .await?
+        .community_pool(at_height(QueryCommunityPoolRequest {}, height))
         .await
     {
-        Ok(b) => {
-            let balance = b.balance.unwrap().amount as u64;
-            update_balance(FOUNDATION_ADDRESS_2, balance).await;
-            info!("foundation wallet 2 balance updated: {}usomm", balance);
+        Ok(r) => {
+            let height = response_height(r.metadata());
+            let amount = get_dec_usomm_amount(r.into_inner().pool, denom)?;
 
-            Ok(())
+            Ok(Observed { amount, height })
         }
         Err(e) => {
+            evict_on_transport_error(endpoint, &e).await;
             bail!(
-                "error querying foundation wallet 2 balance from endpoint {}: {:?}",
+                "error querying community pool from endpoint {}: {:?}",
                 endpoint,
                 e
             );
@@ -85,57 +260,104 @@ pub async fn update_foundation_balance_2(endpoint: &str) -> Result<()> {
     }
 }
 
-/// Periodically updates the cached foundation balance
-pub async fn poll_foundation_balance() -> Result<()> {
-    let period = APP.config().cache.foundation_wallet_update_period;
-    debug!(
-        "updating foundation wallet balance every {} seconds",
-        period
-    );
+/// Queries the total usomm balance in the community pool at `height` from the first endpoint to
+/// respond
+pub async fn fetch_community_pool_balance(
+    source: &dyn BalanceSource,
+    grpc: &GrpcSection,
+    height: Option<u64>,
+) -> Result<Observed> {
+    let method = method_at_height("distribution/CommunityPool", height);
+    race_endpoints(&grpc.endpoints, query_timeout(grpc), |e| {
+        let query = source.community_pool_balance(e, &grpc.base_denom, height);
+        memoized(grpc, e, &method, &[&grpc.base_denom], query).boxed()
+    })
+    .await
+}
 
-    let config = APP.config();
-    // jittered retry with exponential backoff
-    let retry_strategy = ExponentialBackoff::from_millis(500)
-        .map(jitter)
-        .take(config.grpc.failed_query_retries as usize);
-    loop {
-        debug!("updating foundation wallet balance");
-        Retry::spawn(retry_strategy.clone(), || async {
-            for endpoint in config.grpc.endpoints.iter() {
-                if let Err(e) = update_foundation_balance(endpoint).await {
-                    warn!("{e:?}");
-                    continue;
-                }
+/// Updates the cached total usomm balance in the community pool of the chain `config` is for
+pub async fn update_community_pool_balance(
+    source: &dyn BalanceSource,
+    config: &SommStatsConfig,
+) -> Result<()> {
+    let Observed {
+        amount: balance,
+        height,
+    } = timed(
+        "community_pool",
+        fetch_community_pool_balance(source, &config.grpc, None),
+    )
+    .await
+    .wrap_err("failed to query community pool balance from all endpoints")?;
+    let state = ChainState::of(config);
+    update_balance(&state, COMMUNITY_POOL_KEY, balance).await;
+    record_height(&state, COMMUNITY_POOL_KEY, height).await;
+    info!("community pool balance updated: {}usomm", balance);
 
-                if let Err(e) = update_foundation_balance_2(endpoint).await {
-                    warn!("{e:?}");
-                    continue;
-                }
+    Ok(())
+}
 
-                return Ok(());
-            }
+/// Periodically updates the cached community pool balance of `chain`. The config is re-read every
+/// cycle, and a reload starts a new cycle straight away.
+pub async fn poll_community_pool_balance(
+    source: &dyn BalanceSource,
+    chain: Option<&str>,
+) -> Result<()> {
+    debug!(
+        "updating community pool balance every {} seconds",
+        current_chain_config(chain)
+            .cache
+            .community_pool_update_period
+    );
 
-            bail!("failed to query foundation wallet balance from all endpoints");
+    let mut reloads = subscribe_reloads();
+    loop {
+        reloads.borrow_and_update();
+        let config = current_chain_config(chain);
+        let retry_strategy = retry_strategy(
+            config
+                .grpc
+                .community_pool_retries
+                .unwrap_or(config.grpc.failed_query_retries),
+        );
+        debug!("updating community pool balance");
+        Retry::spawn(retry_strategy, || async {
+            update_community_pool_balance(source, &config).await
         })
         .await
-        .unwrap_or_else(|e| error!("{:?}", e));
-        tokio::time::sleep(std::time::Duration::from_secs(period)).await;
+        .unwrap_or_else(|e| log_deduped(Level::Error, format!("{e:?}")));
+        sleep_until_reload(
+            Duration::from_secs(config.cache.community_pool_update_period),
+            &mut reloads,
+        )
+        .await;
     }
 }
 
-/// Updates the cached total usomm balance in the community pool
-pub async fn update_community_pool_balance(endpoint: &str) -> Result<()> {
-    match QueryClient::new(endpoint)?.community_pool().await {
+/// Queries the total bonded tokens in the staking pool
+pub async fn query_bonded_balance(endpoint: &str) -> Result<Observed> {
+    match staking_client(endpoint)
+        .await?
+        .pool(QueryPoolRequest {})
+        .await
+    {
         Ok(r) => {
-            let balance = get_dec_usomm_amount(r);
-            update_balance(COMMUNITY_POOL_KEY, balance).await;
-            info!("community pool balance updated: {}usomm", balance);
+            let height = response_height(r.metadata());
+            let pool = r
+                .into_inner()
+                .pool
+                .ok_or_else(|| eyre!("endpoint {endpoint} returned an empty staking pool"))?;
+            let amount = pool
+                .bonded_tokens
+                .parse::<u64>()
+                .wrap_err_with(|| format!("invalid bonded tokens {:?}", pool.bonded_tokens))?;
 
-            Ok(())
+            Ok(Observed { amount, height })
         }
         Err(e) => {
+            evict_on_transport_error(endpoint, &e).await;
             bail!(
-                "error querying community pool from endpoint {}: {:?}",
+                "error querying staking pool from endpoint {}: {:?}",
                 endpoint,
                 e
             );
@@ -143,47 +365,120 @@ pub async fn update_community_pool_balance(endpoint: &str) -> Result<()> {
     }
 }
 
-/// Periodically updates the cached community pool balance
-pub async fn poll_community_pool_balance() -> Result<()> {
-    let period = APP.config().cache.community_pool_update_period;
-    debug!("updating community pool balance every {} seconds", period);
+/// Updates the cached total bonded balance of the chain `config` is for
+pub async fn update_staking_balance(
+    source: &dyn BalanceSource,
+    config: &SommStatsConfig,
+) -> Result<()> {
+    let grpc = &config.grpc;
+    let Observed {
+        amount: balance,
+        height,
+    } = timed(
+        "staking",
+        race_endpoints(&grpc.endpoints, query_timeout(grpc), |e| {
+            memoized(grpc, e, "staking/Pool", &[], source.bonded_balance(e)).boxed()
+        }),
+    )
+    .await
+    .wrap_err("failed to query bonded balance from all endpoints")?;
+    let state = ChainState::of(config);
+    update_balance(&state, BONDED_KEY, balance).await;
+    record_height(&state, BONDED_KEY, height).await;
+    info!("bonded balance updated: {}usomm", balance);
 
-    let config = APP.config();
-    // jittered retry with exponential backoff
-    let retry_strategy = ExponentialBackoff::from_millis(500)
-        .map(jitter)
-        .take(config.grpc.failed_query_retries as usize);
-    loop {
-        debug!("updating community pool balance");
-        Retry::spawn(retry_strategy.clone(), || async {
-            for endpoint in config.grpc.endpoints.iter() {
-                if let Err(e) = update_community_pool_balance(endpoint).await {
-                    warn!("{e:?}");
-                    continue;
-                }
+    Ok(())
+}
 
-                return Ok(());
-            }
+/// Periodically updates the cached total bonded balance of `chain`. The config is re-read every
+/// cycle, and a reload starts a new cycle straight away.
+pub async fn poll_staking_balance(source: &dyn BalanceSource, chain: Option<&str>) -> Result<()> {
+    debug!(
+        "updating bonded balance every {} seconds",
+        current_chain_config(chain).cache.staking_update_period
+    );
 
-            bail!("failed to query community pool balance from all endpoints");
+    let mut reloads = subscribe_reloads();
+    loop {
+        reloads.borrow_and_update();
+        let config = current_chain_config(chain);
+        let retry_strategy = retry_strategy(
+            config
+                .grpc
+                .staking_retries
+                .unwrap_or(config.grpc.failed_query_retries),
+        );
+        debug!("updating bonded balance");
+        Retry::spawn(retry_strategy, || async {
+            update_staking_balance(source, &config).await
         })
         .await
-        .unwrap_or_else(|e| error!("{:?}", e));
-        tokio::time::sleep(std::time::Duration::from_secs(period)).await;
+        .unwrap_or_else(|e| log_deduped(Level::Error, format!("{e:?}")));
+        sleep_until_reload(
+            Duration::from_secs(config.cache.staking_update_period),
+            &mut reloads,
+        )
+        .await;
     }
 }
 
 /// Queries the balance of the account, which is assumed to be a vesting account, and returns
-/// the portion of the balance that is still vesting (locked)
-pub async fn query_vesting_balance(endpoint: &str, address: &str) -> Result<u64> {
-    let mut qclient = QueryClient::new(endpoint)?;
-    let res = qclient.account_raw(address).await?;
-    let current_time = Utc::now().timestamp();
-    let type_url = &res.type_url;
-    let value: &[u8] = &res.value;
+/// the portion of the balance that is still vesting (locked). When `height` is given the account
+/// is read at that height and the locked portion is calculated as of the block's time.
+pub async fn query_vesting_balance(
+    endpoint: &str,
+    address: &str,
+    denom: &str,
+    at: Option<PastBlock>,
+) -> Result<Observed> {
+    let request = QueryAccountRequest {
+        address: address.to_string(),
+    };
+    let (observed_height, res) = match auth_client(endpoint)
+        .await?
+        .account(at_height(request, at.map(|b| b.height)))
+        .await
+    {
+        Ok(r) => (response_height(r.metadata()), r.into_inner().account),
+        Err(e) => {
+            evict_on_transport_error(endpoint, &e).await;
+            bail!(
+                "error querying account {} from endpoint {}: {:?}",
+                address,
+                endpoint,
+                e
+            );
+        }
+    };
+    let Some(res) = res else {
+        bail!("account {} not found", address);
+    };
+    let current_time = at.map_or_else(|| Utc::now().timestamp(), |b| b.time);
 
     debug!("current time: {current_time}");
 
+    let locked_balance = calculate_locked_balance(&res, current_time, denom)
+        .wrap_err_with(|| format!("failed to calculate locked balance of {address}"))?;
+
+    info!("locked balance for {address} is {locked_balance}");
+
+    // so we can remove the address from the query list when it's done vesting
+    if locked_balance == 0 {
+        warn!("{} has 0 locked", address);
+    }
+
+    Ok(Observed {
+        amount: locked_balance,
+        height: observed_height,
+    })
+}
+
+/// Decodes a vesting account and returns the portion of its original vesting balance that is still
+/// locked at `current_time`
+pub fn calculate_locked_balance(account: &Any, current_time: i64, denom: &str) -> Result<u64> {
+    let type_url = &account.type_url;
+    let value: &[u8] = &account.value;
+
     // get the still-vesting (locked) balance of the account
     let locked_balance = match type_url.as_str() {
         CONTINUOUS_VESTING_ACCOUNT_TYPE_URL => {
@@ -194,18 +489,20 @@ pub async fn query_vesting_balance(endpoint: &str, address: &str) -> Result<u64>
                 account.start_time,
                 account.base_vesting_account.clone().unwrap().end_time
             );
-            if account.start_time > current_time {
-                0_u64
-            } else {
-                let base = account.base_vesting_account.clone().unwrap();
-                let original_vesting = get_usomm_amount(base.original_vesting);
-                let unlocked_proportion = (current_time - account.start_time) as f64
-                    / (base.end_time - account.start_time) as f64;
+            let base = account.base_vesting_account.unwrap();
+            let original_vesting = get_usomm_amount(base.original_vesting, denom)?;
 
-                (original_vesting as f64 * (1.0 - unlocked_proportion)) as u64
-            }
+            continuous_locked_balance(
+                original_vesting,
+                account.start_time,
+                base.end_time,
+                current_time,
+            )
         }
         PERIODIC_VESTING_ACCOUNT_TYPE_URL => {
+            // periods unlock in steps, not linearly: a period's whole amount stays locked until
+            // the period ends and unlocks all at once at its end time, matching the SDK which
+            // treats a period as vested once the block time reaches start + length
             let account = PeriodicVestingAccount::decode(value)?;
             let periods = account.vesting_periods;
             let mut locked_balance: u64 = 0;
@@ -218,10 +515,10 @@ pub async fn query_vesting_balance(endpoint: &str, address: &str) -> Result<u64>
                     start_time + period.length,
                     period.length
                 );
-                locked_balance += if current_time > start_time + period.length {
+                locked_balance += if current_time >= start_time + period.length {
                     0
                 } else {
-                    get_usomm_amount(period.amount)
+                    get_usomm_amount(period.amount, denom)?
                 };
 
                 start_time += period.length;
@@ -237,99 +534,1245 @@ pub async fn query_vesting_balance(endpoint: &str, address: &str) -> Result<u64>
             let locked_balance = if current_time > base.end_time {
                 0
             } else {
-                get_usomm_amount(base.original_vesting)
+                get_usomm_amount(base.original_vesting, denom)?
             };
 
             debug!("delayed vesting account locked balance {locked_balance}");
             locked_balance
         }
+        BASE_VESTING_ACCOUNT_TYPE_URL => {
+            let base = BaseVestingAccount::decode(value)?;
+
+            debug!("base vesting account end time: {}", base.end_time);
+            let locked_balance = if current_time > base.end_time {
+                0
+            } else {
+                get_usomm_amount(base.original_vesting, denom)?
+            };
+
+            debug!("base vesting account locked balance {locked_balance}");
+            locked_balance
+        }
+        PERMANENT_LOCKED_ACCOUNT_TYPE_URL => {
+            let account = PermanentLockedAccount::decode(value)?;
+            let base = account.base_vesting_account.unwrap();
+
+            // permanently locked accounts never vest, regardless of end time
+            let locked_balance = get_usomm_amount(base.original_vesting, denom)?;
+
+            debug!("permanent locked account locked balance {locked_balance}");
+            locked_balance
+        }
         _ => {
-            bail!(
-                "vesting account {} is of an unhandled type: {}",
-                address,
-                type_url
-            );
+            bail!("vesting account is of an unhandled type: {}", type_url);
         }
     };
 
-    info!("locked balance for {address} is {locked_balance}");
+    Ok(locked_balance)
+}
 
-    // so we can remove the address from the query list when it's done vesting
-    if locked_balance == 0 {
-        warn!("{} has 0 locked", address);
+/// Returns the portion of a continuous vest that is still locked at `current_time`. Nothing has
+/// unlocked at or before `start_time`, everything has unlocked at or after `end_time`, and in
+/// between the locked amount decreases linearly.
+pub fn continuous_locked_balance(
+    original_vesting: u64,
+    start_time: i64,
+    end_time: i64,
+    current_time: i64,
+) -> u64 {
+    if current_time >= end_time {
+        return 0;
+    }
+    if current_time <= start_time {
+        return original_vesting;
     }
 
-    Ok(locked_balance)
+    let unlocked_proportion =
+        ((current_time - start_time) as f64 / (end_time - start_time) as f64).clamp(0.0, 1.0);
+
+    (original_vesting as f64 * (1.0 - unlocked_proportion)) as u64
 }
 
-/// Periodically updates the cached total vesting balance
-pub async fn poll_vesting_balance() -> Result<()> {
-    let period = APP.config().cache.vesting_update_period;
-    debug!("updating vesting balance every {} seconds", period);
+/// Queries the locked balance of a vesting account as of block `at` from the first endpoint to
+/// respond
+pub async fn fetch_vesting_balance(
+    source: &dyn BalanceSource,
+    grpc: &GrpcSection,
+    address: &str,
+    at: Option<PastBlock>,
+) -> Result<Observed> {
+    let method = method_at_height("auth/Account", at.map(|b| b.height));
+    race_endpoints(&grpc.endpoints, query_timeout(grpc), |e| {
+        let query = source
+            .vesting_balance(e, address, &grpc.base_denom, at)
+            .map(move |r| r.wrap_err_with(|| format!("endpoint {e}")));
+        memoized(grpc, e, &method, &[address, &grpc.base_denom], query).boxed()
+    })
+    .await
+}
 
-    let config = APP.config();
-    // jittered retry with exponential backoff
-    let retry_strategy = ExponentialBackoff::from_millis(500)
-        .map(jitter)
-        .take(config.grpc.failed_query_retries as usize);
+/// Updates the cached locked balance of a vesting account of the chain `config` is for, returning
+/// the new balance
+pub async fn update_vesting_balance(
+    source: &dyn BalanceSource,
+    config: &SommStatsConfig,
+    address: &str,
+) -> Result<u64> {
+    let Observed {
+        amount: balance,
+        height,
+    } = timed(
+        "vesting",
+        fetch_vesting_balance(source, &config.grpc, address, None),
+    )
+    .await
+    .wrap_err_with(|| format!("failed to query vesting balance of {address} from all endpoints"))?;
+    let state = ChainState::of(config);
+    update_balance(&state, address, balance).await;
+    record_height(&state, address, height).await;
+
+    Ok(balance)
+}
+
+/// Periodically updates the cached vesting balances of `chain`. Accounts that report 0 locked for
+/// `vesting_drop_threshold` consecutive cycles are no longer queried, and keep their cached 0
+/// balance. Up to `max_concurrent_vesting_queries` accounts are queried at once, each with its own
+/// retries. The config is re-read every cycle, and a reload starts a new cycle straight away.
+pub async fn poll_vesting_balance(source: &dyn BalanceSource, chain: Option<&str>) -> Result<()> {
+    debug!(
+        "updating vesting balance every {} seconds",
+        current_chain_config(chain).cache.vesting_update_period
+    );
+
+    // number of consecutive cycles each account has reported 0 locked
+    let mut zero_cycles: HashMap<String, u32> = HashMap::new();
+    let mut reloads = subscribe_reloads();
     loop {
-        debug!("updating vesting balances");
-        for address in VESTING_ACCOUNTS {
-            Retry::spawn(retry_strategy.clone(), || async {
-                for endpoint in config.grpc.endpoints.iter() {
-                    match query_vesting_balance(endpoint, address).await {
-                        Ok(b) => {
-                            update_balance(address, b).await;
-                            return Ok(());
-                        }
-                        Err(e) => {
-                            warn!("{:?}", e);
-                            continue;
-                        }
+        reloads.borrow_and_update();
+        let config = current_chain_config(chain);
+        update_vesting_balances(source, &config, &mut zero_cycles).await;
+        sleep_until_reload(
+            Duration::from_secs(config.cache.vesting_update_period),
+            &mut reloads,
+        )
+        .await;
+    }
+}
+
+/// Runs one vesting poll cycle, updating every vesting account that hasn't been dropped.
+/// `zero_cycles` tracks how many consecutive cycles each account has reported 0 locked.
+pub async fn update_vesting_balances(
+    source: &dyn BalanceSource,
+    config: &SommStatsConfig,
+    zero_cycles: &mut HashMap<String, u32>,
+) {
+    let retry_strategy = retry_strategy(
+        config
+            .grpc
+            .vesting_retries
+            .unwrap_or(config.grpc.failed_query_retries),
+    );
+    let drop_threshold = config.cache.vesting_drop_threshold;
+    debug!("updating vesting balances");
+    let (addresses, dropped): (Vec<String>, Vec<String>) = config
+        .accounting
+        .vesting_accounts()
+        .into_iter()
+        .partition(|a| {
+            drop_threshold == 0 || zero_cycles.get(a).copied().unwrap_or(0) < drop_threshold
+        });
+    // a dropped account has finished vesting, so its cached 0 is still current. Touch it so the
+    // freshness checks don't see it as stale.
+    let state = ChainState::of(config);
+    for address in dropped {
+        update_balance(&state, &address, 0).await;
+    }
+    let mut results = stream::iter(addresses)
+        .map(|address| {
+            let retry_strategy = retry_strategy.clone();
+            async move {
+                let result = Retry::spawn(retry_strategy, || async {
+                    update_vesting_balance(source, config, &address).await
+                })
+                .await;
+
+                (address, result)
+            }
+        })
+        .buffer_unordered(config.grpc.max_concurrent_vesting_queries.max(1));
+
+    while let Some((address, result)) = results.next().await {
+        match result {
+            Ok(0) => {
+                let count = zero_cycles.entry(address.clone()).or_default();
+                *count += 1;
+                if *count == drop_threshold {
+                    info!(
+                        "{} has had 0 locked for {} cycles, no longer querying it",
+                        address, drop_threshold
+                    );
+                }
+            }
+            Ok(_) => {
+                zero_cycles.remove(&address);
+            }
+            Err(e) => log_deduped(Level::Error, format!("{e:?}")),
+        }
+    }
+}
+
+/// Updates every cached balance once, without retries, returning each balance key with the result
+/// of its update. Vesting accounts the poller has stopped querying are refreshed as well.
+pub async fn refresh_balances(
+    source: &dyn BalanceSource,
+    config: &SommStatsConfig,
+) -> Vec<(String, Result<()>)> {
+    let mut results = vec![
+        (
+            COMMUNITY_POOL_KEY.to_string(),
+            update_community_pool_balance(source, config).await,
+        ),
+        (
+            BONDED_KEY.to_string(),
+            update_staking_balance(source, config).await,
+        ),
+    ];
+    for address in config.accounting.foundation_accounts() {
+        let result = update_foundation_balance(source, config, &address).await;
+        results.push((address, result));
+    }
+    let vesting: Vec<(String, Result<()>)> = stream::iter(config.accounting.vesting_accounts())
+        .map(|address| async move {
+            let result = update_vesting_balance(source, config, &address)
+                .await
+                .map(|_| ());
+            (address, result)
+        })
+        .buffer_unordered(config.grpc.max_concurrent_vesting_queries.max(1))
+        .collect()
+        .await;
+    results.extend(vesting);
+
+    results
+}
+
+/// Queries every balance in the supply breakdown at `height`, keyed like the balances cache. The
+/// balances cache is left untouched. Up to `max_concurrent_vesting_queries` accounts are queried
+/// at once, and the first failure fails the whole lookup. State at a past height can't change, so
+/// results for the last `historical_heights` heights are reused.
+pub async fn query_balances_at_height(
+    source: &dyn BalanceSource,
+    config: &SommStatsConfig,
+    height: u64,
+) -> Result<HashMap<String, u64>> {
+    // (address, whether it's a vesting account)
+    let accounts: Vec<(String, bool)> = config
+        .accounting
+        .foundation_accounts()
+        .into_iter()
+        .map(|a| (a, false))
+        .chain(
+            config
+                .accounting
+                .subtracted_vesting_accounts()
+                .into_iter()
+                .map(|a| (a, true)),
+        )
+        .collect();
+    let state = ChainState::of(config);
+    if let Some((_, balances)) = state.historical.read().await.iter().find(|(h, b)| {
+        *h == height
+            && b.contains_key(COMMUNITY_POOL_KEY)
+            && accounts.iter().all(|(a, _)| b.contains_key(a))
+    }) {
+        return Ok(balances.clone());
+    }
+
+    let grpc = &config.grpc;
+    let time = race_endpoints(&grpc.endpoints, query_timeout(grpc), |e| {
+        source
+            .block_time(e, height)
+            .map(move |r| r.wrap_err_with(|| format!("endpoint {e}")))
+            .boxed()
+    })
+    .await
+    .wrap_err_with(|| format!("failed to query block time at {height}"))?;
+    let at = Some(PastBlock { height, time });
+
+    let mut balances = HashMap::new();
+    balances.insert(
+        COMMUNITY_POOL_KEY.to_string(),
+        fetch_community_pool_balance(source, grpc, Some(height))
+            .await
+            .wrap_err_with(|| format!("failed to query community pool balance at {height}"))?
+            .amount,
+    );
+
+    let mut results = stream::iter(accounts)
+        .map(|(address, vesting)| async move {
+            let balance = if vesting {
+                fetch_vesting_balance(source, grpc, &address, at).await
+            } else {
+                fetch_balance(source, grpc, &address, Some(height)).await
+            };
+
+            (address, balance)
+        })
+        .buffer_unordered(grpc.max_concurrent_vesting_queries.max(1));
+    while let Some((address, balance)) = results.next().await {
+        let balance = balance
+            .wrap_err_with(|| format!("failed to query balance of {address} at {height}"))?;
+        balances.insert(address, balance.amount);
+    }
+
+    let limit = config.cache.historical_heights;
+    if limit > 0 {
+        let mut cached = state.historical.write().await;
+        cached.retain(|(h, _)| *h != height);
+        while cached.len() >= limit {
+            cached.pop_front();
+        }
+        cached.push_back((height, balances.clone()));
+    }
+
+    Ok(balances)
+}
+
+/// Returns the result of an identical query to the same endpoint if one succeeded within
+/// `query_cache_ttl_secs`, otherwise runs the query and remembers its result. This only dedupes
+/// near-simultaneous queries, the balance caches are still the source of truth.
+pub fn memoized<'a, F>(
+    grpc: &GrpcSection,
+    endpoint: &str,
+    method: &str,
+    args: &[&str],
+    query: F,
+) -> impl Future<Output = Result<Observed>> + 'a
+where
+    F: Future<Output = Result<Observed>> + 'a,
+{
+    let ttl = Duration::from_secs(grpc.query_cache_ttl_secs);
+    let key = format!("{endpoint}|{method}|{}", args.join(","));
+
+    async move {
+        if ttl.is_zero() {
+            return query.await;
+        }
+
+        if let Some((result, queried_at)) = QUERY_RESULTS.read().await.get(&key) {
+            if queried_at.elapsed() < ttl {
+                debug!("reusing recent result of {}", key);
+                return Ok(*result);
+            }
+            increment_counter!(CACHE_EXPIRED_TOTAL, "cache" => "query_results");
+        }
+
+        let result = query.await?;
+        let mut results = QUERY_RESULTS.write().await;
+        results.retain(|_, (_, queried_at)| queried_at.elapsed() < ttl);
+        results.insert(key, (result, Instant::now()));
+
+        Ok(result)
+    }
+}
+
+/// Runs the query of a balance update, recording how long it took, including racing the
+/// endpoints, under the `update` label
+async fn timed<T>(update: &'static str, query: impl Future<Output = Result<T>>) -> Result<T> {
+    let start = Instant::now();
+    let result = query.await;
+    histogram!(
+        UPDATE_DURATION_SECONDS,
+        start.elapsed().as_secs_f64(),
+        "update" => update,
+        "result" => if result.is_ok() { "success" } else { "failure" }
+    );
+
+    result
+}
+
+/// Name a query is memoized under, qualified with the height when querying a past block so its
+/// result isn't mixed up with the latest one
+fn method_at_height(method: &str, height: Option<u64>) -> String {
+    match height {
+        Some(height) => format!("{method}@{height}"),
+        None => method.to_string(),
+    }
+}
+
+/// Logs a query failure at `level`, suppressing identical repeats so that an outage doesn't flood
+/// the logs
+fn log_deduped(level: Level, message: String) {
+    if let Some(line) = LOG_DEDUPER.check(message, Instant::now()) {
+        log!(level, "{}", line);
+    }
+}
+
+fn query_timeout(grpc: &GrpcSection) -> Duration {
+    Duration::from_secs(grpc.query_timeout_secs)
+}
+
+/// Queries the available endpoints according to the configured [`EndpointStrategy`]. Under the
+/// ordered strategy the query is raced against every available endpoint concurrently and the
+/// first successful result is returned, cancelling the queries still in flight. Under the
+/// round-robin and random strategies endpoints are tried one at a time, starting from the endpoint
+/// chosen by the selector, so that load is spread across nodes. Endpoints that are cooling down
+/// after repeated failures are skipped. A query that doesn't complete within `timeout` counts as a
+/// failure. Individual failures are logged, and if every query fails the last error is returned.
+pub async fn race_endpoints<'a, T, F>(
+    endpoints: &'a [String],
+    timeout: Duration,
+    query: F,
+) -> Result<T>
+where
+    T: Send + 'a,
+    F: Fn(&'a str) -> BoxFuture<'a, Result<T>>,
+{
+    if endpoints.is_empty() {
+        bail!("no endpoints to query");
+    }
+
+    let available = ENDPOINT_HEALTH.read().await.available(endpoints);
+    let (strategy, ordered) = {
+        let selector = ENDPOINT_SELECTOR.read().await;
+        (selector.strategy(), selector.order(available))
+    };
+    let attempt = |e: &'a str| {
+        tokio::time::timeout(timeout, query(e))
+            .map(move |r| {
+                r.unwrap_or_else(|_| {
+                    Err(eyre!("query to endpoint {e} timed out after {timeout:?}"))
+                })
+            })
+            .then(move |r| async move {
+                let mut health = ENDPOINT_HEALTH.write().await;
+                match &r {
+                    Ok(_) => {
+                        increment_counter!(QUERY_SUCCESS_TOTAL, "endpoint" => e.to_string());
+                        health.record_success(e);
+                    }
+                    Err(err) => {
+                        log_deduped(Level::Warn, format!("{err:?}"));
+                        increment_counter!(QUERY_FAILURE_TOTAL, "endpoint" => e.to_string());
+                        health.record_failure(e);
                     }
                 }
 
-                bail!(
-                    "failed to query vesting balance of {} from all endpoints",
-                    address
-                );
+                r
             })
+            .boxed()
+    };
+
+    if strategy == EndpointStrategy::Ordered {
+        return select_ok(ordered.into_iter().map(|e| attempt(e)))
             .await
-            .unwrap_or_else(|e| error!("{:?}", e));
+            .map(|(r, _)| r);
+    }
+
+    let mut last_err = None;
+    for e in ordered {
+        match attempt(e).await {
+            Ok(r) => return Ok(r),
+            Err(err) => last_err = Some(err),
         }
-        tokio::time::sleep(std::time::Duration::from_secs(period)).await;
     }
+
+    Err(last_err.unwrap_or_else(|| eyre!("no endpoints to query")))
 }
 
-/// Converts [`Vec<Coin>`] to the sum of the contained usomm amounts
-pub fn get_usomm_amount(coins: Vec<Coin>) -> u64 {
+/// Converts [`Vec<Coin>`] to the sum of the contained amounts of the base denom (usomm on
+/// mainnet). Returns an error if any such amount can't be parsed or the sum overflows.
+pub fn get_usomm_amount(coins: Vec<Coin>, denom: &str) -> Result<u64> {
     coins
         .iter()
-        .filter_map(|c| {
-            if c.denom == USOMM {
-                Some(c.amount.parse::<u64>().unwrap())
-            } else {
-                None
-            }
+        .filter(|c| c.denom == denom)
+        .try_fold(0_u64, |sum, c| {
+            let amount = c
+                .amount
+                .parse::<u64>()
+                .wrap_err_with(|| format!("invalid usomm amount {:?}", c.amount))?;
+
+            sum.checked_add(amount)
+                .ok_or_else(|| eyre!("usomm amount overflowed summing {:?}", c.amount))
         })
-        .sum()
 }
 
-pub fn get_dec_usomm_amount(coins: Vec<DecCoin>) -> u64 {
+/// Converts [`Vec<DecCoin>`] to the sum of the contained whole amounts of the base denom (usomm
+/// on mainnet). Returns an error if any such amount can't be parsed or the sum overflows.
+pub fn get_dec_usomm_amount(coins: Vec<DecCoin>, denom: &str) -> Result<u64> {
     coins
         .iter()
-        .filter_map(|c| {
-            if c.denom == USOMM {
-                let truncated = &c.amount[0..c.amount.len() - 18];
-                Some(truncated.parse::<u64>().unwrap())
-            } else {
-                None
-            }
+        .filter(|c| c.denom == denom)
+        .try_fold(0_u64, |sum, c| {
+            let amount = parse_sdk_dec(&c.amount)?;
+
+            sum.checked_add(amount)
+                .ok_or_else(|| eyre!("usomm amount overflowed summing {:?}", c.amount))
         })
-        .sum()
 }
 
-pub async fn update_balance(key: &str, value: u64) {
-    BALANCES.lock().await.insert(key.to_string(), value);
+/// Parses the whole part of an SDK decimal, discarding the fractional part. The proto encoding is
+/// an integer scaled by 10^18 with no separator, so values with 18 or fewer digits are less than
+/// one. Amounts rendered with a `.` separator are also accepted.
+pub fn parse_sdk_dec(amount: &str) -> Result<u64> {
+    if amount.is_empty() || !amount.is_ascii() {
+        bail!("invalid decimal amount {:?}", amount);
+    }
+
+    let (whole, fractional) = match amount.split_once('.') {
+        Some((whole, fractional)) => (whole, fractional),
+        None if amount.len() > SDK_DEC_PRECISION => {
+            amount.split_at(amount.len() - SDK_DEC_PRECISION)
+        }
+        None => ("0", amount),
+    };
+
+    if !fractional.chars().all(|c| c.is_ascii_digit()) {
+        bail!("invalid decimal amount {:?}", amount);
+    }
+
+    whole
+        .parse::<u64>()
+        .wrap_err_with(|| format!("invalid decimal amount {:?}", amount))
+}
+
+/// Queries the latest block height
+pub async fn query_latest_height(endpoint: &str) -> Result<u64> {
+    match tendermint_client(endpoint)
+        .await?
+        .get_latest_block(GetLatestBlockRequest {})
+        .await
+    {
+        Ok(r) => {
+            let height = r
+                .into_inner()
+                .block
+                .and_then(|b| b.header)
+                .map(|h| h.height)
+                .ok_or_else(|| eyre!("endpoint {endpoint} returned a block without a header"))?;
+
+            u64::try_from(height).wrap_err_with(|| format!("invalid block height {height}"))
+        }
+        Err(e) => {
+            evict_on_transport_error(endpoint, &e).await;
+            bail!(
+                "error querying latest block from endpoint {}: {:?}",
+                endpoint,
+                e
+            );
+        }
+    }
+}
+
+/// Queries the time of the block at `height` as a Unix timestamp
+pub async fn query_block_time(endpoint: &str, height: u64) -> Result<i64> {
+    let request = GetBlockByHeightRequest {
+        height: i64::try_from(height).wrap_err_with(|| format!("invalid block height {height}"))?,
+    };
+    match tendermint_client(endpoint)
+        .await?
+        .get_block_by_height(request)
+        .await
+    {
+        Ok(r) => r
+            .into_inner()
+            .block
+            .and_then(|b| b.header)
+            .and_then(|h| h.time)
+            .map(|t| t.seconds)
+            .ok_or_else(|| eyre!("endpoint {endpoint} returned block {height} without a time")),
+        Err(e) => {
+            evict_on_transport_error(endpoint, &e).await;
+            bail!(
+                "error querying block {} from endpoint {}: {:?}",
+                height,
+                endpoint,
+                e
+            );
+        }
+    }
+}
+
+/// Queries the latest block height from every endpoint concurrently, returning the endpoints that
+/// responded within the query timeout and those that didn't
+pub async fn probe_endpoints(grpc: &GrpcSection) -> (Vec<String>, Vec<String>) {
+    let timeout = query_timeout(grpc);
+    let results = join_all(grpc.endpoints.iter().map(|e| async move {
+        match tokio::time::timeout(timeout, query_latest_height(e)).await {
+            Ok(Ok(_)) => true,
+            Ok(Err(err)) => {
+                debug!("health probe of {} failed: {:?}", e, err);
+                false
+            }
+            Err(_) => {
+                debug!("health probe of {} timed out after {:?}", e, timeout);
+                false
+            }
+        }
+    }))
+    .await;
+
+    let (reachable, unreachable): (Vec<_>, Vec<_>) = grpc
+        .endpoints
+        .iter()
+        .cloned()
+        .zip(results)
+        .partition(|(_, ok)| *ok);
+
+    (
+        reachable.into_iter().map(|(e, _)| e).collect(),
+        unreachable.into_iter().map(|(e, _)| e).collect(),
+    )
+}
+
+/// Seconds an endpoint probe is reused, so that `/healthz` can't be used to fan out queries to
+/// every endpoint
+pub const PROBE_TTL_SECS: u64 = 5;
+
+/// Result of the last [`probe_endpoints`]
+#[derive(Clone, Debug)]
+pub struct EndpointProbe {
+    pub endpoints: Vec<String>,
+    pub probed_at: Instant,
+    pub reachable: Vec<String>,
+    pub unreachable: Vec<String>,
+}
+
+/// Like [`probe_endpoints`], but reuses a probe of the same endpoints from the last
+/// [`PROBE_TTL_SECS`] seconds. Concurrent callers wait for a single probe instead of each starting
+/// their own.
+pub async fn cached_probe_endpoints(grpc: &GrpcSection) -> (Vec<String>, Vec<String>) {
+    // held across the probe so concurrent callers share it
+    let mut last = ENDPOINT_PROBE.write().await;
+    if let Some(probe) = last.as_ref() {
+        if probe.endpoints == grpc.endpoints
+            && probe.probed_at.elapsed() < Duration::from_secs(PROBE_TTL_SECS)
+        {
+            return (probe.reachable.clone(), probe.unreachable.clone());
+        }
+    }
+
+    let (reachable, unreachable) = probe_endpoints(grpc).await;
+    *last = Some(EndpointProbe {
+        endpoints: grpc.endpoints.clone(),
+        probed_at: Instant::now(),
+        reachable: reachable.clone(),
+        unreachable: unreachable.clone(),
+    });
+
+    (reachable, unreachable)
+}
+
+/// Queries the latest block and the balance of `address` once from every endpoint concurrently,
+/// returning each endpoint with the height it reported or why it failed. A height of 0 is treated
+/// as a failure since it means the node isn't serving chain data.
+pub async fn self_test(
+    source: &dyn BalanceSource,
+    grpc: &GrpcSection,
+    address: &str,
+) -> Vec<(String, Result<u64>)> {
+    let timeout = query_timeout(grpc);
+    let results = join_all(grpc.endpoints.iter().map(|e| async move {
+        let check = async {
+            let height = source.latest_height(e).await?;
+            if height == 0 {
+                bail!("endpoint reported a block height of 0");
+            }
+            source
+                .balance(e, address, &grpc.base_denom, None)
+                .await
+                .wrap_err_with(|| format!("failed to query balance of {address}"))?;
+
+            Ok(height)
+        };
+
+        tokio::time::timeout(timeout, check)
+            .await
+            .unwrap_or_else(|_| Err(eyre!("timed out after {:?}", timeout)))
+    }))
+    .await;
+
+    grpc.endpoints.iter().cloned().zip(results).collect()
+}
+
+/// Records the block height the node reported answering the balance query at. Nodes that don't
+/// report one leave the balance without a height, rather than keeping a height it wasn't read at.
+async fn record_height(state: &ChainState, key: &str, height: Option<u64>) {
+    let mut heights = state.heights.write().await;
+    match height {
+        Some(height) => heights.insert(key.to_string(), height),
+        None => heights.remove(key),
+    };
+}
+
+/// Caches a balance of the chain `state` belongs to and marks it as refreshed now
+pub async fn update_balance(state: &ChainState, key: &str, value: u64) {
+    state.balances.write().await.insert(key.to_string(), value);
+    state
+        .updated_at
+        .write()
+        .await
+        .insert(key.to_string(), SystemTime::now());
+    let now = Utc::now().timestamp() as f64;
+    match &state.name {
+        None => gauge!(BALANCE_LAST_UPDATED, now, "key" => key.to_string()),
+        Some(chain) => gauge!(
+            BALANCE_LAST_UPDATED,
+            now,
+            "key" => key.to_string(),
+            "chain" => chain.clone()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::{BALANCES, BALANCES_HEIGHT, USOMM};
+    use crate::source::{MockSource, MOCK_HEIGHT};
+    use assay::assay;
+    use ocular::cosmrs::proto::cosmos::vesting::v1beta1::Period;
+    use std::sync::atomic::Ordering;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn endpoints(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("http://endpoint{i}")).collect()
+    }
+
+    fn observed(amount: u64) -> Observed {
+        Observed {
+            amount,
+            height: Some(1),
+        }
+    }
+
+    fn coin(denom: &str, amount: &str) -> Coin {
+        Coin {
+            denom: denom.to_string(),
+            amount: amount.to_string(),
+        }
+    }
+
+    #[test]
+    fn get_usomm_amount_sums_usomm_only() {
+        let coins = vec![coin(USOMM, "100"), coin("uatom", "5"), coin(USOMM, "23")];
+
+        assert_eq!(123, get_usomm_amount(coins, USOMM).unwrap());
+    }
+
+    #[test]
+    fn get_usomm_amount_malformed() {
+        assert!(get_usomm_amount(vec![coin(USOMM, "12abc")], USOMM).is_err());
+        assert!(get_usomm_amount(vec![coin(USOMM, "18446744073709551616")], USOMM).is_err());
+        assert!(get_usomm_amount(
+            vec![coin(USOMM, &u64::MAX.to_string()), coin(USOMM, "1")],
+            USOMM
+        )
+        .is_err());
+        // malformed amounts of other denoms are ignored
+        assert_eq!(
+            1,
+            get_usomm_amount(vec![coin("uatom", "x"), coin(USOMM, "1")], USOMM).unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_sdk_dec_scaled_integers() {
+        assert_eq!(0, parse_sdk_dec("0").unwrap());
+        assert_eq!(0, parse_sdk_dec("500").unwrap());
+        assert_eq!(0, parse_sdk_dec("999999999999999999").unwrap());
+        assert_eq!(1, parse_sdk_dec("1000000000000000000").unwrap());
+        assert_eq!(1000, parse_sdk_dec("1000000000000000000000").unwrap());
+    }
+
+    #[test]
+    fn parse_sdk_dec_with_separator() {
+        assert_eq!(0, parse_sdk_dec("0.5").unwrap());
+        assert_eq!(1234, parse_sdk_dec("1234.000000000000000001").unwrap());
+        assert_eq!(1234, parse_sdk_dec("1234.").unwrap());
+    }
+
+    #[test]
+    fn parse_sdk_dec_malformed() {
+        assert!(parse_sdk_dec("").is_err());
+        assert!(parse_sdk_dec("abc").is_err());
+        assert!(parse_sdk_dec("1.2.3").is_err());
+        assert!(parse_sdk_dec("-1.0").is_err());
+        assert!(parse_sdk_dec("1000000000000000000é").is_err());
+    }
+
+    #[test]
+    fn get_dec_usomm_amount_sums_usomm_only() {
+        let coins = vec![
+            DecCoin {
+                denom: USOMM.to_string(),
+                amount: "2000000000000000000000".to_string(),
+            },
+            DecCoin {
+                denom: "uatom".to_string(),
+                amount: "5".to_string(),
+            },
+            DecCoin {
+                denom: USOMM.to_string(),
+                amount: "500".to_string(),
+            },
+        ];
+
+        assert_eq!(2000, get_dec_usomm_amount(coins, USOMM).unwrap());
+    }
+
+    #[test]
+    fn continuous_locked_balance_over_time() {
+        assert_eq!(1000, continuous_locked_balance(1000, 100, 200, 50));
+        assert_eq!(1000, continuous_locked_balance(1000, 100, 200, 100));
+        assert_eq!(750, continuous_locked_balance(1000, 100, 200, 125));
+        assert_eq!(0, continuous_locked_balance(1000, 100, 200, 200));
+    }
+
+    #[test]
+    fn continuous_locked_balance_past_end_time() {
+        assert_eq!(0, continuous_locked_balance(1000, 100, 200, 201));
+        assert_eq!(0, continuous_locked_balance(u64::MAX, 100, 200, i64::MAX));
+    }
+
+    fn base_vesting_account(original_vesting: u64, end_time: i64) -> BaseVestingAccount {
+        BaseVestingAccount {
+            original_vesting: vec![coin(USOMM, &original_vesting.to_string())],
+            end_time,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn calculate_locked_balance_permanent_locked_never_decays() {
+        let account = PermanentLockedAccount {
+            base_vesting_account: Some(base_vesting_account(1000, 100)),
+        };
+        let account = Any {
+            type_url: PERMANENT_LOCKED_ACCOUNT_TYPE_URL.to_string(),
+            value: account.encode_to_vec(),
+        };
+
+        for current_time in [0, 100, 101, i64::MAX] {
+            assert_eq!(
+                1000,
+                calculate_locked_balance(&account, current_time, USOMM).unwrap()
+            );
+        }
+    }
+
+    fn periodic_vesting_account(start_time: i64, periods: &[(i64, u64)]) -> Any {
+        let account = PeriodicVestingAccount {
+            base_vesting_account: Some(base_vesting_account(
+                periods.iter().map(|(_, amount)| amount).sum(),
+                start_time + periods.iter().map(|(length, _)| length).sum::<i64>(),
+            )),
+            start_time,
+            vesting_periods: periods
+                .iter()
+                .map(|(length, amount)| Period {
+                    length: *length,
+                    amount: vec![coin(USOMM, &amount.to_string())],
+                })
+                .collect(),
+        };
+
+        Any {
+            type_url: PERIODIC_VESTING_ACCOUNT_TYPE_URL.to_string(),
+            value: account.encode_to_vec(),
+        }
+    }
+
+    #[test]
+    fn calculate_locked_balance_periodic_unlocks_in_steps() {
+        let account = periodic_vesting_account(100, &[(100, 1000), (100, 500)]);
+
+        assert_eq!(1500, calculate_locked_balance(&account, 50, USOMM).unwrap());
+        // nothing unlocks partway through a period
+        assert_eq!(
+            1500,
+            calculate_locked_balance(&account, 199, USOMM).unwrap()
+        );
+        assert_eq!(500, calculate_locked_balance(&account, 250, USOMM).unwrap());
+        assert_eq!(0, calculate_locked_balance(&account, 301, USOMM).unwrap());
+    }
+
+    #[test]
+    fn calculate_locked_balance_periodic_unlocks_at_period_end() {
+        let account = periodic_vesting_account(100, &[(100, 1000), (100, 500)]);
+
+        assert_eq!(500, calculate_locked_balance(&account, 200, USOMM).unwrap());
+        assert_eq!(0, calculate_locked_balance(&account, 300, USOMM).unwrap());
+    }
+
+    #[test]
+    fn calculate_locked_balance_unhandled_type() {
+        let account = Any {
+            type_url: "/cosmos.auth.v1beta1.BaseAccount".to_string(),
+            value: Vec::new(),
+        };
+
+        assert!(calculate_locked_balance(&account, 0, USOMM).is_err());
+    }
+
+    #[assay]
+    async fn race_endpoints_returns_first_success() {
+        let endpoints = endpoints(3);
+        let result = race_endpoints(&endpoints, TIMEOUT, |e| {
+            async move {
+                match e {
+                    "http://endpoint0" => Err(eyre!("endpoint down")),
+                    "http://endpoint1" => {
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                        Ok(1)
+                    }
+                    _ => Ok(2),
+                }
+            }
+            .boxed()
+        })
+        .await;
+
+        assert_eq!(2, result.unwrap());
+    }
+
+    #[assay]
+    async fn race_endpoints_all_fail() {
+        let endpoints = endpoints(2);
+        let result: Result<u64> = race_endpoints(&endpoints, TIMEOUT, |e| {
+            async move { Err(eyre!("{e} down")) }.boxed()
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert!(race_endpoints(&[], TIMEOUT, |_| async { Ok(0) }.boxed())
+            .await
+            .is_err());
+    }
+
+    #[assay]
+    async fn race_endpoints_times_out_hung_endpoint() {
+        let endpoints = endpoints(2);
+        let result = race_endpoints(&endpoints, Duration::from_millis(50), |e| {
+            async move {
+                if e == "http://endpoint0" {
+                    std::future::pending::<()>().await;
+                }
+
+                Err(eyre!("{e} down"))
+            }
+            .boxed()
+        })
+        .await;
+
+        let err: Result<u64> = result;
+        assert!(err.is_err());
+        let report = ENDPOINT_HEALTH.read().await.report(&endpoints);
+        assert_eq!(1, report[0].consecutive_failures);
+        assert_eq!(1, report[1].consecutive_failures);
+    }
+
+    #[assay]
+    async fn race_endpoints_skips_degraded_endpoints() {
+        let endpoints = endpoints(2);
+        {
+            let mut health = ENDPOINT_HEALTH.write().await;
+            health.configure(&GrpcSection {
+                endpoint_failure_threshold: 1,
+                ..Default::default()
+            });
+            health.record_failure("http://endpoint0");
+        }
+
+        let result = race_endpoints(&endpoints, TIMEOUT, |e| {
+            async move {
+                if e == "http://endpoint0" {
+                    panic!("degraded endpoint should be skipped");
+                }
+
+                Ok(1)
+            }
+            .boxed()
+        })
+        .await;
+
+        assert_eq!(1, result.unwrap());
+        let report = ENDPOINT_HEALTH.read().await.report(&endpoints);
+        assert!(!report[0].healthy);
+        assert!(report[1].healthy);
+    }
+
+    #[assay]
+    async fn race_endpoints_round_robin_spreads_queries() {
+        let endpoints = endpoints(3);
+        ENDPOINT_SELECTOR.write().await.configure(&GrpcSection {
+            endpoint_strategy: EndpointStrategy::RoundRobin,
+            ..Default::default()
+        });
+
+        let mut queried = Vec::new();
+        for _ in 0..3 {
+            let endpoint = race_endpoints(&endpoints, TIMEOUT, |e| {
+                async move { Ok(e.to_string()) }.boxed()
+            })
+            .await
+            .unwrap();
+            queried.push(endpoint);
+        }
+
+        assert_eq!(endpoints, queried);
+    }
+
+    #[assay]
+    async fn race_endpoints_round_robin_falls_back_on_failure() {
+        let endpoints = endpoints(2);
+        ENDPOINT_SELECTOR.write().await.configure(&GrpcSection {
+            endpoint_strategy: EndpointStrategy::RoundRobin,
+            ..Default::default()
+        });
+
+        let result = race_endpoints(&endpoints, TIMEOUT, |e| {
+            async move {
+                match e {
+                    "http://endpoint0" => Err(eyre!("endpoint down")),
+                    _ => Ok(1),
+                }
+            }
+            .boxed()
+        })
+        .await;
+
+        assert_eq!(1, result.unwrap());
+        let report = ENDPOINT_HEALTH.read().await.report(&endpoints);
+        assert_eq!(1, report[0].consecutive_failures);
+    }
+
+    #[assay]
+    async fn self_test_reports_each_endpoint() {
+        let grpc = GrpcSection {
+            endpoints: endpoints(2),
+            ..Default::default()
+        };
+        let source = MockSource {
+            balances: HashMap::from([("somm1a".to_string(), 1)]),
+            ..Default::default()
+        };
+
+        let results = self_test(&source, &grpc, "somm1a").await;
+        assert_eq!(2, results.len());
+        assert_eq!("http://endpoint0", results[0].0);
+        assert_eq!(100, *results[0].1.as_ref().unwrap());
+
+        let results = self_test(&source, &grpc, "somm1b").await;
+        assert!(results.iter().all(|(_, r)| r.is_err()));
+    }
+
+    #[assay]
+    async fn update_records_height_balance_was_read_at() {
+        let mut config = SommStatsConfig::default();
+        config.grpc.endpoints = endpoints(1);
+        let source = MockSource {
+            balances: HashMap::from([("somm1v".to_string(), 5)]),
+            ..Default::default()
+        };
+
+        update_vesting_balance(&source, &config, "somm1v")
+            .await
+            .unwrap();
+        assert_eq!(
+            Some(&MOCK_HEIGHT),
+            BALANCES_HEIGHT.read().await.get("somm1v")
+        );
+
+        record_height(&ChainState::of(&config), "somm1v", None).await;
+        assert!(BALANCES_HEIGHT.read().await.get("somm1v").is_none());
+    }
+
+    #[assay]
+    async fn update_vesting_balances_queries_every_account() {
+        let mut config = SommStatsConfig::default();
+        config.grpc.endpoints = endpoints(1);
+        config.grpc.max_concurrent_vesting_queries = 2;
+        config.cache.vesting_drop_threshold = 1;
+        config.accounting.vesting_addresses = (0..5).map(|i| format!("somm1v{i}")).collect();
+        let mut balances: HashMap<String, u64> = config
+            .accounting
+            .vesting_addresses
+            .iter()
+            .map(|a| (a.clone(), 10))
+            .collect();
+        balances.insert("somm1v0".to_string(), 0);
+        let source = MockSource {
+            balances,
+            ..Default::default()
+        };
+
+        let mut zero_cycles = HashMap::new();
+        update_vesting_balances(&source, &config, &mut zero_cycles).await;
+
+        let cached = BALANCES.read().await.clone();
+        assert_eq!(Some(&0), cached.get("somm1v0"));
+        for i in 1..5 {
+            assert_eq!(Some(&10), cached.get(&format!("somm1v{i}")));
+        }
+        assert_eq!(HashMap::from([("somm1v0".to_string(), 1)]), zero_cycles);
+    }
+
+    #[assay]
+    async fn query_balances_at_height_skips_cache() {
+        let mut config = SommStatsConfig::default();
+        config.grpc.endpoints = endpoints(1);
+        config.accounting.foundation_addresses = vec!["somm1f".to_string()];
+        config.accounting.vesting_addresses = vec!["somm1v0".to_string(), "somm1v1".to_string()];
+        let mut balances = HashMap::from([
+            (COMMUNITY_POOL_KEY.to_string(), 1),
+            ("somm1f".to_string(), 2),
+            ("somm1v0".to_string(), 3),
+        ]);
+        let source = MockSource {
+            balances: balances.clone(),
+            ..Default::default()
+        };
+        assert!(query_balances_at_height(&source, &config, 100)
+            .await
+            .is_err());
+
+        balances.insert("somm1v1".to_string(), 4);
+        let source = MockSource {
+            balances: balances.clone(),
+            ..Default::default()
+        };
+        assert_eq!(
+            balances,
+            query_balances_at_height(&source, &config, 100)
+                .await
+                .unwrap()
+        );
+        assert!(BALANCES.read().await.is_empty());
+    }
+
+    #[assay]
+    async fn query_balances_at_height_reuses_block_time_and_results() {
+        let mut config = SommStatsConfig::default();
+        config.grpc.endpoints = endpoints(1);
+        config.accounting.foundation_addresses = vec!["somm1f".to_string()];
+        config.accounting.vesting_addresses = vec!["somm1v0".to_string(), "somm1v1".to_string()];
+        config.cache.historical_heights = 1;
+        let balances = HashMap::from([
+            (COMMUNITY_POOL_KEY.to_string(), 1),
+            ("somm1f".to_string(), 2),
+            ("somm1v0".to_string(), 3),
+            ("somm1v1".to_string(), 4),
+        ]);
+        let source = MockSource {
+            balances: balances.clone(),
+            ..Default::default()
+        };
+
+        for _ in 0..2 {
+            assert_eq!(
+                balances,
+                query_balances_at_height(&source, &config, 100)
+                    .await
+                    .unwrap()
+            );
+        }
+        assert_eq!(1, source.block_time_queries.load(Ordering::Relaxed));
+
+        // only the latest height is kept
+        query_balances_at_height(&source, &config, 101)
+            .await
+            .unwrap();
+        query_balances_at_height(&source, &config, 100)
+            .await
+            .unwrap();
+        assert_eq!(3, source.block_time_queries.load(Ordering::Relaxed));
+    }
+
+    #[assay]
+    async fn refresh_balances_reports_each_key() {
+        let mut config = SommStatsConfig::default();
+        config.grpc.endpoints = endpoints(1);
+        config.accounting.foundation_addresses = vec!["somm1f".to_string()];
+        config.accounting.vesting_addresses = vec!["somm1v0".to_string(), "somm1v1".to_string()];
+        let source = MockSource {
+            balances: HashMap::from([
+                (COMMUNITY_POOL_KEY.to_string(), 1),
+                (BONDED_KEY.to_string(), 2),
+                ("somm1f".to_string(), 3),
+                ("somm1v0".to_string(), 4),
+            ]),
+            ..Default::default()
+        };
+
+        let results: HashMap<String, bool> = refresh_balances(&source, &config)
+            .await
+            .into_iter()
+            .map(|(k, r)| (k, r.is_ok()))
+            .collect();
+        assert_eq!(
+            HashMap::from([
+                (COMMUNITY_POOL_KEY.to_string(), true),
+                (BONDED_KEY.to_string(), true),
+                ("somm1f".to_string(), true),
+                ("somm1v0".to_string(), true),
+                ("somm1v1".to_string(), false),
+            ]),
+            results
+        );
+        assert_eq!(Some(&4), BALANCES.read().await.get("somm1v0"));
+    }
+
+    #[assay]
+    async fn memoized_reuses_recent_results() {
+        let grpc = GrpcSection::default();
+        let first = memoized(
+            &grpc,
+            "http://endpoint0",
+            "bank/Balance",
+            &["somm1a"],
+            async { Ok(observed(1)) },
+        )
+        .await;
+        assert_eq!(1, first.unwrap().amount);
+
+        let second = memoized(
+            &grpc,
+            "http://endpoint0",
+            "bank/Balance",
+            &["somm1a"],
+            async {
+                panic!("recent result should be reused");
+            },
+        )
+        .await;
+        assert_eq!(1, second.unwrap().amount);
+
+        // different arguments are queried
+        let other = memoized(
+            &grpc,
+            "http://endpoint0",
+            "bank/Balance",
+            &["somm1b"],
+            async { Ok(observed(2)) },
+        )
+        .await;
+        assert_eq!(2, other.unwrap().amount);
+
+        // a ttl of 0 always queries
+        let grpc = GrpcSection {
+            query_cache_ttl_secs: 0,
+            ..Default::default()
+        };
+        let disabled = memoized(
+            &grpc,
+            "http://endpoint0",
+            "bank/Balance",
+            &["somm1a"],
+            async { Ok(observed(3)) },
+        )
+        .await;
+        assert_eq!(3, disabled.unwrap().amount);
+    }
 }
-//
@@ -0,0 +1,107 @@
+//! Per-client rate limiting
+//!
+//! Each client IP gets a token bucket that holds up to one second's worth of requests and refills
+//! continuously at the configured rate. Requests that find the bucket empty are rejected with the
+//! time until the next token is available.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Buckets are pruned once this many clients are being tracked
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    requests_per_sec: u32,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_sec: u32) -> Self {
+        Self {
+            requests_per_sec,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token for the client, or returns how long it must wait before its next request
+    pub fn check(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let rate = self.requests_per_sec as f64;
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            // clients whose buckets have refilled are indistinguishable from new ones
+            buckets.retain(|_, b| b.refilled(rate, now) < rate);
+        }
+
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: rate,
+            last_refill: now,
+        });
+        bucket.tokens = bucket.refilled(rate, now);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+}
+
+impl Bucket {
+    fn refilled(&self, rate: f64, now: Instant) -> f64 {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+
+        (self.tokens + elapsed * rate).min(rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT: IpAddr = IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+
+    #[test]
+    fn allows_burst_then_rejects() {
+        let limiter = RateLimiter::new(2);
+        let now = Instant::now();
+
+        assert!(limiter.check(CLIENT, now).is_ok());
+        assert!(limiter.check(CLIENT, now).is_ok());
+
+        let retry_after = limiter.check(CLIENT, now).unwrap_err();
+        assert_eq!(Duration::from_millis(500), retry_after);
+
+        // other clients have their own bucket
+        let other = IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 1));
+        assert!(limiter.check(other, now).is_ok());
+    }
+
+    #[test]
+    fn refills_over_time() {
+        let limiter = RateLimiter::new(1);
+        let now = Instant::now();
+
+        assert!(limiter.check(CLIENT, now).is_ok());
+        assert!(limiter.check(CLIENT, now).is_err());
+        assert!(limiter
+            .check(CLIENT, now + Duration::from_millis(500))
+            .is_err());
+        assert!(limiter.check(CLIENT, now + Duration::from_secs(1)).is_ok());
+    }
+}
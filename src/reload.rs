@@ -0,0 +1,287 @@
+//! Config reloading
+//!
+//! Abscissa's config cell can only be set once, so the running config lives in [`LIVE_CONFIG`]
+//! instead. On SIGHUP the config file is re-read, validated and swapped in. The pollers are woken
+//! to start a new cycle with the new endpoints, update periods and address lists, and the API
+//! handlers pick it up on their next request. Settings baked into the listener, router and snapshot
+//! task only take effect after a restart, and changes to them are reported as such.
+
+use std::{
+    path::Path,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use abscissa_core::{
+    path::AbsPathBuf,
+    tracing::log::{error, info, warn},
+    Application, Config,
+};
+use abscissa_tokio::tokio::{self, sync::watch};
+use axum::extract::FromRef;
+use eyre::{bail, Result, WrapErr};
+use lazy_static::lazy_static;
+use serde_json::Value;
+
+use crate::{
+    application::{CHANNELS, CONNECTION_SETTINGS, ENDPOINT_HEALTH, ENDPOINT_SELECTOR, LOG_DEDUPER},
+    config::{self, SommStatsConfig},
+    prelude::APP,
+};
+
+/// Server settings read on every request, which therefore don't need a restart
+const LIVE_SERVER_SETTINGS: &[&str] = &[
+    "max_bulk_addresses",
+    "public_base_url",
+    "historical_queries",
+];
+
+lazy_static! {
+    /// The running config, initialized from the config loaded at startup
+    pub static ref LIVE_CONFIG: SharedConfig = SharedConfig::new(APP.config());
+
+    /// Signalled every time a new config is stored in [`LIVE_CONFIG`]
+    static ref RELOADS: watch::Sender<()> = watch::channel(()).0;
+}
+
+/// The current config
+pub fn current_config() -> Arc<SommStatsConfig> {
+    LIVE_CONFIG.load()
+}
+
+/// The current config of `chain`, see [`SommStatsConfig::chain`], or of the default chain for
+/// `None`
+pub fn current_chain_config(chain: Option<&str>) -> Arc<SommStatsConfig> {
+    LIVE_CONFIG.for_chain(chain).load()
+}
+
+/// Subscribes to config reloads. A reload is reported to the receiver if it happens after the
+/// receiver last marked the reloads seen.
+pub fn subscribe_reloads() -> watch::Receiver<()> {
+    RELOADS.subscribe()
+}
+
+/// Sleeps for `period`, returning early if the config is reloaded in the meantime
+pub async fn sleep_until_reload(period: Duration, reloads: &mut watch::Receiver<()>) {
+    tokio::select! {
+        _ = tokio::time::sleep(period) => {}
+        _ = reloads.changed() => {}
+    }
+}
+
+/// A config that can be swapped while the app is running. Handlers extract the current
+/// `Arc<SommStatsConfig>` from it on every request, scoped to the chain the routes are for.
+#[derive(Clone, Debug)]
+pub struct SharedConfig {
+    config: Arc<RwLock<Arc<SommStatsConfig>>>,
+    chain: Option<String>,
+}
+
+impl SharedConfig {
+    pub fn new(config: Arc<SommStatsConfig>) -> Self {
+        Self {
+            config: Arc::new(RwLock::new(config)),
+            chain: None,
+        }
+    }
+
+    /// The same config, loaded as the config of `chain` or of the default chain for `None`
+    pub fn for_chain(&self, chain: Option<&str>) -> Self {
+        Self {
+            config: self.config.clone(),
+            chain: chain.map(String::from),
+        }
+    }
+
+    pub fn load(&self) -> Arc<SommStatsConfig> {
+        let config = self.config.read().unwrap().clone();
+        match &self.chain {
+            None => config,
+            Some(name) => Arc::new(
+                config
+                    .chain(name)
+                    .expect("chains are only added or removed on restart"),
+            ),
+        }
+    }
+
+    /// Replaces the whole config, including every chain
+    pub fn store(&self, config: Arc<SommStatsConfig>) {
+        *self.config.write().unwrap() = config;
+    }
+}
+
+impl From<Arc<SommStatsConfig>> for SharedConfig {
+    fn from(config: Arc<SommStatsConfig>) -> Self {
+        Self::new(config)
+    }
+}
+
+impl FromRef<SharedConfig> for Arc<SommStatsConfig> {
+    fn from_ref(shared: &SharedConfig) -> Self {
+        shared.load()
+    }
+}
+
+/// Reads, validates and applies the config file, returning the changed settings that only take
+/// effect after a restart. The running config is left untouched if the new one is invalid.
+pub async fn reload_config(path: &Path) -> Result<Vec<String>> {
+    let path = AbsPathBuf::canonicalize(path)
+        .wrap_err_with(|| format!("failed to resolve config file {}", path.display()))?;
+    let new = SommStatsConfig::load_toml_file(&path)?;
+    let new = config::apply_env_overrides(new, |name| std::env::var(name).ok())?;
+    config::validate(&new)?;
+
+    let old = LIVE_CONFIG.load();
+    if !old.chains.keys().eq(new.chains.keys()) {
+        bail!("chains can't be added or removed without a restart");
+    }
+    let restart_required = restart_required(&old, &new);
+
+    ENDPOINT_HEALTH.write().await.configure(&new.grpc);
+    ENDPOINT_SELECTOR.write().await.configure(&new.grpc);
+    LOG_DEDUPER.configure(&new.grpc);
+    if keepalive_changed(&old, &new) {
+        // reconnect so the new keepalive settings apply to every connection
+        *CONNECTION_SETTINGS.write().await = (&new.grpc).into();
+        CHANNELS.write().await.clear();
+    }
+    LIVE_CONFIG.store(Arc::new(new));
+    RELOADS.send_replace(());
+
+    Ok(restart_required)
+}
+
+/// Settings that differ between the configs but can't be applied to the running app
+pub fn restart_required(old: &SommStatsConfig, new: &SommStatsConfig) -> Vec<String> {
+    let mut changed: Vec<String> = changed_keys(&old.server, &new.server)
+        .into_iter()
+        .filter(|k| !LIVE_SERVER_SETTINGS.contains(&k.as_str()))
+        .map(|k| format!("server.{k}"))
+        .collect();
+    if old.cache.snapshot_file != new.cache.snapshot_file {
+        changed.push("cache.snapshot_file".to_string());
+    }
+    if old.cache.snapshot_interval_secs != new.cache.snapshot_interval_secs {
+        changed.push("cache.snapshot_interval_secs".to_string());
+    }
+
+    changed
+}
+
+fn keepalive_changed(old: &SommStatsConfig, new: &SommStatsConfig) -> bool {
+    old.grpc.keepalive_interval_secs != new.grpc.keepalive_interval_secs
+        || old.grpc.keepalive_timeout_secs != new.grpc.keepalive_timeout_secs
+        || old.grpc.keepalive_while_idle != new.grpc.keepalive_while_idle
+}
+
+/// Top-level keys whose values differ between two serialized config sections
+fn changed_keys(old: &impl serde::Serialize, new: &impl serde::Serialize) -> Vec<String> {
+    let (Ok(Value::Object(old)), Ok(Value::Object(new))) =
+        (serde_json::to_value(old), serde_json::to_value(new))
+    else {
+        return Vec::new();
+    };
+
+    old.into_iter()
+        .filter(|(k, v)| new.get(k) != Some(v))
+        .map(|(k, _)| k)
+        .collect()
+}
+
+/// Reloads the config file every time the process receives SIGHUP. Failed reloads are logged and
+/// the running config is kept.
+pub async fn reload_on_sighup() -> Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let Some(path) = APP.config_path() else {
+            info!("no config file loaded, SIGHUP won't reload the config");
+            return Ok(());
+        };
+        let mut hangup = signal(SignalKind::hangup())?;
+        while hangup.recv().await.is_some() {
+            info!("received SIGHUP, reloading config from {}", path.display());
+            match reload_config(&path).await {
+                Ok(restart_required) if restart_required.is_empty() => info!("config reloaded"),
+                Ok(restart_required) => warn!(
+                    "config reloaded, but these settings only take effect after a restart: {}",
+                    restart_required.join(", ")
+                ),
+                Err(e) => error!(
+                    "failed to reload config, keeping the running config: {:?}",
+                    e
+                ),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assay::assay;
+
+    #[test]
+    fn restart_required_lists_static_settings() {
+        let old = SommStatsConfig::default();
+        let mut new = SommStatsConfig::default();
+        new.grpc.endpoints = vec!["http://localhost:9090".to_string()];
+        new.cache.vesting_update_period = 60;
+        new.server.max_bulk_addresses = 10;
+        assert!(restart_required(&old, &new).is_empty());
+
+        new.server.port = 9000;
+        new.cache.snapshot_interval_secs = 0;
+        assert_eq!(
+            vec![
+                "server.port".to_string(),
+                "cache.snapshot_interval_secs".to_string()
+            ],
+            restart_required(&old, &new)
+        );
+    }
+
+    #[assay]
+    async fn sleep_until_reload_wakes_on_reload() {
+        let mut reloads = subscribe_reloads();
+        let sleep = tokio::spawn(async move {
+            sleep_until_reload(Duration::from_secs(3600), &mut reloads).await
+        });
+        RELOADS.send_replace(());
+
+        tokio::time::timeout(Duration::from_secs(5), sleep)
+            .await
+            .expect("sleep wasn't woken by the reload")
+            .unwrap();
+    }
+
+    #[test]
+    fn shared_config_loads_chain() {
+        let mut config = SommStatsConfig::default();
+        config
+            .chains
+            .insert("hub".to_string(), config::ChainSection::default());
+        config.chains.get_mut("hub").unwrap().grpc.base_denom = "uatom".to_string();
+        let shared = SharedConfig::new(Arc::new(config));
+
+        let chain = shared.for_chain(Some("hub")).load();
+        assert_eq!(Some("hub"), chain.chain.as_deref());
+        assert_eq!("uatom", chain.grpc.base_denom);
+        assert_eq!(None, shared.for_chain(None).load().chain);
+    }
+
+    #[test]
+    fn shared_config_extracts_latest() {
+        let shared = SharedConfig::new(Arc::new(SommStatsConfig::default()));
+        let mut config = SommStatsConfig::default();
+        config.server.port = 9000;
+        shared.store(Arc::new(config));
+
+        let extracted = Arc::<SommStatsConfig>::from_ref(&shared);
+        assert_eq!(9000, extracted.server.port);
+    }
+}
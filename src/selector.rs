@@ -0,0 +1,112 @@
+//! gRPC endpoint selection
+//!
+//! Decides the order in which healthy endpoints are tried so that, under the round-robin and
+//! random strategies, load is spread across nodes instead of landing on the first endpoint in the
+//! list.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::config::{EndpointStrategy, GrpcSection};
+
+/// Orders endpoints according to the configured [`EndpointStrategy`]
+#[derive(Debug, Default)]
+pub struct EndpointSelector {
+    strategy: EndpointStrategy,
+    next: AtomicUsize,
+}
+
+impl EndpointSelector {
+    pub fn new(strategy: EndpointStrategy) -> Self {
+        Self {
+            strategy,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Applies the strategy from the gRPC config
+    pub fn configure(&mut self, config: &GrpcSection) {
+        self.strategy = config.endpoint_strategy;
+    }
+
+    pub fn strategy(&self) -> EndpointStrategy {
+        self.strategy
+    }
+
+    /// Returns the endpoints in the order they should be tried. Under the ordered strategy this is
+    /// list order; otherwise the list is rotated to start from the next or a random endpoint.
+    pub fn order<'a>(&self, mut endpoints: Vec<&'a String>) -> Vec<&'a String> {
+        if endpoints.is_empty() {
+            return endpoints;
+        }
+
+        let len = endpoints.len();
+        let start = match self.strategy {
+            EndpointStrategy::Ordered => 0,
+            EndpointStrategy::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed),
+            EndpointStrategy::Random => random_index(),
+        };
+        endpoints.rotate_left(start % len);
+
+        endpoints
+    }
+}
+
+/// A random index, using the randomly seeded std hasher to avoid pulling in an RNG crate
+fn random_index() -> usize {
+    RandomState::new().build_hasher().finish() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoints() -> Vec<String> {
+        (0..3).map(|i| format!("http://endpoint{i}")).collect()
+    }
+
+    #[test]
+    fn ordered_keeps_list_order() {
+        let endpoints = endpoints();
+        let selector = EndpointSelector::new(EndpointStrategy::Ordered);
+
+        for _ in 0..3 {
+            assert_eq!(
+                endpoints.iter().collect::<Vec<_>>(),
+                selector.order(endpoints.iter().collect())
+            );
+        }
+    }
+
+    #[test]
+    fn round_robin_rotates_starting_endpoint() {
+        let endpoints = endpoints();
+        let selector = EndpointSelector::new(EndpointStrategy::RoundRobin);
+
+        let firsts: Vec<&String> = (0..4)
+            .map(|_| selector.order(endpoints.iter().collect())[0])
+            .collect();
+
+        assert_eq!(
+            vec![&endpoints[0], &endpoints[1], &endpoints[2], &endpoints[0]],
+            firsts
+        );
+        let order = selector.order(endpoints.iter().collect());
+        assert_eq!(vec![&endpoints[1], &endpoints[2], &endpoints[0]], order);
+    }
+
+    #[test]
+    fn random_returns_every_endpoint() {
+        let endpoints = endpoints();
+        let selector = EndpointSelector::new(EndpointStrategy::Random);
+
+        let mut order = selector.order(endpoints.iter().collect());
+        order.sort();
+
+        assert_eq!(endpoints.iter().collect::<Vec<_>>(), order);
+        assert!(selector.order(Vec::new()).is_empty());
+    }
+}
@@ -1,94 +1,2721 @@
-use std::net::SocketAddr;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    future::Future,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use abscissa_core::tracing::{
     info,
     log::{error, warn},
 };
+use abscissa_tokio::tokio;
 use axum::{
-    http::StatusCode,
+    extract::{ConnectInfo, MatchedPath, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, Method, Request, StatusCode},
+    middleware::{self, Next},
     response::{IntoResponse, Response},
-    routing::get,
-    Router,
+    routing::{get, post},
+    Json, Router,
 };
-use eyre::Result;
+use eyre::{Result, WrapErr};
+use futures::{stream, FutureExt, StreamExt};
+use metrics::gauge;
+use ocular::cosmrs::AccountId;
 use serde::{Deserialize, Serialize};
+use tower_http::{
+    compression::CompressionLayer,
+    cors::{self, CorsLayer},
+    timeout::TimeoutLayer,
+};
+use utoipa::{OpenApi, ToSchema};
 
 use crate::{
-    accounting::{FOUNDATION_ADDRESS, FOUNDATION_ADDRESS_2, TOTAL_USOMM_SUPPLY, VESTING_ACCOUNTS},
-    application::BALANCES,
-    query::COMMUNITY_POOL_KEY,
+    application::{
+        ChainState, ACCOUNT_PREFIX, BALANCES, BALANCES_UPDATED_AT, CHANNELS, ENDPOINT_HEALTH,
+        LOOKUP_BALANCES,
+    },
+    config::{ServerSection, SommStatsConfig},
+    error::{SommStatsError, QUERY_FAILED, RATE_LIMITED},
+    history::supply_history,
+    openapi::ApiDoc,
+    query::{
+        cached_probe_endpoints, lookup_balance, query_balances_at_height, refresh_balances,
+        BONDED_KEY, COMMUNITY_POOL_KEY,
+    },
+    rate_limit::RateLimiter,
+    reload::{SharedConfig, LIVE_CONFIG},
+    source::GrpcSource,
+    telemetry::{self, CIRCULATING_SUPPLY},
 };
 
-pub async fn listen(addr: SocketAddr) -> Result<()> {
-    let app = Router::new()
-        .route("/", get(|| async { StatusCode::OK }))
-        .route("/v1/circulating-supply", get(get_circulating_supply));
+pub async fn listen(addr: SocketAddr, shutdown: impl Future<Output = ()>) -> Result<()> {
+    let config = LIVE_CONFIG.load();
+    let app = router(LIVE_CONFIG.clone())?;
+
+    let mut server = axum::Server::bind(&addr);
+    // close connections that trickle in their headers, so slow clients can't exhaust connections
+    if config.server.header_read_timeout_secs > 0 {
+        server = server
+            .http1_header_read_timeout(Duration::from_secs(config.server.header_read_timeout_secs));
+    }
+    if config.server.tcp_keepalive_secs > 0 {
+        server = server.tcp_keepalive(Some(Duration::from_secs(config.server.tcp_keepalive_secs)));
+    }
 
     info!("listening on {}", addr);
-    Ok(axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await?)
+    let shutdown = shutdown.shared();
+    let serve = server
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown.clone());
+    tokio::pin!(serve);
+
+    tokio::select! {
+        result = &mut serve => return Ok(result?),
+        _ = shutdown => {}
+    }
+
+    // the server has stopped accepting connections, give in-flight requests a chance to finish
+    let grace_period = Duration::from_secs(config.server.shutdown_grace_period_secs);
+    info!(
+        "draining in-flight requests for up to {} seconds",
+        grace_period.as_secs()
+    );
+    match tokio::time::timeout(grace_period, serve).await {
+        Ok(result) => Ok(result?),
+        Err(_) => {
+            warn!("shutdown grace period elapsed with requests still in flight");
+            Ok(())
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Resolves when the process receives SIGTERM or Ctrl-C
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("failed to listen for Ctrl-C: {:?}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("failed to listen for SIGTERM: {:?}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    info!("received shutdown signal");
+}
+
+/// Builds the API router with its middleware. Handlers and cache lifetimes read the current
+/// config on each request, but the middleware is configured once from the config at build time.
+pub fn router(config: impl Into<SharedConfig>) -> Result<Router> {
+    let shared: SharedConfig = config.into();
+    let config = shared.load();
+    let cors = cors_layer(&config.server)?;
+    let compression = config.server.compression;
+    let request_timeout = config.server.request_timeout_secs;
+    let rate_limit = config.server.rate_limit_per_sec;
+    let max_age = |period: fn(&SommStatsConfig) -> u64| {
+        let shared = shared.clone();
+        middleware::map_response(move |response: Response| {
+            let secs = period(&shared.load());
+            async move { with_max_age(response, secs) }
+        })
+    };
+
+    // routes served for every chain, under /v1 for the default chain and /v1/<name> for the
+    // chains under [chains]. get routes also answer HEAD requests.
+    let chain_routes = |prefix: &str| {
+        Router::new()
+            .route(
+                &format!("{prefix}/circulating-supply"),
+                get(get_circulating_supply).layer(max_age(supply_max_age)),
+            )
+            .route(
+                &format!("{prefix}/total-supply"),
+                get(get_total_supply).layer(max_age(|_| TOTAL_SUPPLY_MAX_AGE)),
+            )
+            .route(
+                &format!("{prefix}/staked-supply"),
+                get(get_staked_supply).layer(max_age(|c| c.cache.staking_update_period)),
+            )
+            .route(
+                &format!("{prefix}/bonded-ratio"),
+                get(get_bonded_ratio).layer(max_age(|c| c.cache.staking_update_period)),
+            )
+            .route(
+                &format!("{prefix}/community-pool"),
+                get(get_community_pool).layer(max_age(|c| c.cache.community_pool_update_period)),
+            )
+            .route(
+                &format!("{prefix}/supply-breakdown"),
+                get(get_supply_breakdown).layer(max_age(supply_max_age)),
+            )
+            .route(
+                &format!("{prefix}/vesting"),
+                get(get_vesting).layer(max_age(|c| c.cache.vesting_update_period)),
+            )
+            .route(
+                &format!("{prefix}/supply-freshness"),
+                get(get_supply_freshness),
+            )
+    };
+
+    let mut v1 = chain_routes("/v1")
+        .route("/v1/endpoints/health", get(get_endpoints_health))
+        .route("/v1/balance/:address", get(get_account_balance))
+        .route("/v1/balances", post(post_balances))
+        .route(
+            "/v1/circulating-supply/history",
+            get(get_circulating_supply_history),
+        );
+    for name in config.chains.keys() {
+        let chain = shared.for_chain(Some(name));
+        v1 = v1.merge(chain_routes(&format!("/v1/{name}")).with_state(chain));
+    }
+    if rate_limit > 0 {
+        let limiter = Arc::new(RateLimiter::new(rate_limit));
+        v1 = v1.route_layer(middleware::from_fn_with_state(limiter, limit_rate));
+    }
+
+    let mut router = Router::new()
+        .route("/", get(|| async { StatusCode::OK }))
+        .route("/readyz", get(get_readiness))
+        .route("/healthz", get(get_healthz))
+        .route("/version", get(get_version))
+        .route("/metrics", get(get_metrics))
+        .route("/openapi.json", get(get_openapi))
+        .merge(v1);
+    if config.server.debug_endpoints {
+        router = router.route("/debug/caches", get(get_debug_caches));
+    }
+    if let Some(token) = &config.server.admin_token {
+        let token = Arc::new(token.clone());
+        router = router.route(
+            "/admin/refresh/:cache",
+            post(post_admin_refresh)
+                .route_layer(middleware::from_fn_with_state(token, require_admin_token)),
+        );
+    }
+    router = router.layer(cors);
+    if compression {
+        // the default predicate leaves tiny bodies like the plain text supply figures uncompressed
+        router = router.layer(CompressionLayer::new());
+    }
+    if request_timeout > 0 {
+        // inside the request logging so timed out requests are still logged
+        router = router.layer(TimeoutLayer::new(Duration::from_secs(request_timeout)));
+    }
+
+    Ok(router
+        .layer(middleware::from_fn(log_request))
+        .with_state(shared))
+}
+
+/// Seconds the total supply may be cached for. It only changes when the config does.
+const TOTAL_SUPPLY_MAX_AGE: u64 = 3600;
+
+/// Seconds the circulating supply may be cached for, the shortest update period of the balances
+/// it is derived from
+fn supply_max_age(config: &SommStatsConfig) -> u64 {
+    let cache = &config.cache;
+
+    cache
+        .community_pool_update_period
+        .min(cache.vesting_update_period)
+        .min(cache.foundation_wallet_update_period)
+}
+
+/// Lets clients and CDNs cache successful responses for `max_age` seconds, which should match how
+/// often the underlying balances are refreshed. Errors aren't cacheable so a 503 during startup
+/// doesn't stick.
+fn with_max_age(mut response: Response, max_age: u64) -> Response {
+    if response.status().is_success() {
+        if let Ok(value) = HeaderValue::from_str(&format!("public, max-age={max_age}")) {
+            response.headers_mut().insert(header::CACHE_CONTROL, value);
+        }
+    }
+
+    response
+}
+
+/// Rejects requests with a 429 once the client exceeds its rate limit. Requests without a known
+/// peer address aren't limited.
+async fn limit_rate<B>(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if let Some(ConnectInfo(peer)) = request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        if let Err(retry_after) = limiter.check(peer.ip(), Instant::now()) {
+            // Retry-After is in whole seconds, so round up
+            let retry_after = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            let mut response = error_response(
+                StatusCode::TOO_MANY_REQUESTS,
+                RATE_LIMITED,
+                format!("rate limit exceeded, retry after {retry_after}s"),
+            );
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+            return response;
+        }
+    }
+
+    next.run(request).await
+}
+
+/// Rejects requests with a 401 unless they carry `Authorization: Bearer <admin token>`
+async fn require_admin_token<B>(
+    State(token): State<Arc<String>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|t| constant_time_eq(t.as_bytes(), token.as_bytes()));
+    if !authorized {
+        return SommStatsError::Unauthorized.into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Compares two byte strings in time independent of where they differ, so the admin token can't
+/// be guessed byte by byte from response times
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Logs the method, matched route, status and latency of every request
+async fn log_request<B>(request: Request<B>, next: Next<B>) -> Response {
+    let method = request.method().clone();
+    // log the route template rather than the raw path so lines can be grepped by route
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let start = Instant::now();
+
+    let response = next.run(request).await;
+    info!(
+        "request method={} route={} status={} latency_ms={}",
+        method,
+        route,
+        response.status().as_u16(),
+        start.elapsed().as_millis()
+    );
+
+    response
+}
+
+/// Allows cross-origin GET requests from the configured origins, so the API can be called from
+/// browser dashboards
+fn cors_layer(server: &ServerSection) -> Result<CorsLayer> {
+    let cors = CorsLayer::new()
+        .allow_methods([Method::GET, Method::HEAD, Method::POST])
+        .allow_headers([header::CONTENT_TYPE]);
+    if server.allowed_origins.iter().any(|o| o == "*") {
+        return Ok(cors.allow_origin(cors::Any));
+    }
+
+    let origins = server
+        .allowed_origins
+        .iter()
+        .map(|o| {
+            o.parse::<HeaderValue>()
+                .wrap_err_with(|| format!("invalid allowed origin {o:?}"))
+        })
+        .collect::<Result<Vec<HeaderValue>>>()?;
+
+    Ok(cors.allow_origin(origins))
+}
+
+/// Query parameters accepted by the supply endpoints
+#[derive(Debug, Default, Deserialize)]
+pub struct SupplyParams {
+    pub format: Option<String>,
+}
+
+/// Representations the dual-format endpoints can respond with
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Representation {
+    Text,
+    Json,
+}
+
+impl Representation {
+    /// Picks the representation for a request. An explicit `?format=` wins, otherwise the
+    /// highest quality of `text/plain` and `application/json` in the `Accept` header is used.
+    /// Falls back to text, which is what CoinGecko and other listing sites expect.
+    pub fn negotiate(accept: Option<&str>, params: &SupplyParams) -> Self {
+        match params.format.as_deref() {
+            Some("json") => return Representation::Json,
+            Some("text") => return Representation::Text,
+            _ => (),
+        }
+        let Some(accept) = accept else {
+            return Representation::Text;
+        };
+
+        let mut text_q = 0.0_f32;
+        let mut json_q = 0.0_f32;
+        for range in accept.split(',') {
+            let mut parts = range.split(';').map(str::trim);
+            let media_type = parts.next().unwrap_or_default().to_ascii_lowercase();
+            let q = parts
+                .find_map(|p| p.strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            match media_type.as_str() {
+                "application/json" | "application/*" => json_q = json_q.max(q),
+                "text/plain" | "text/*" | "*/*" => text_q = text_q.max(q),
+                _ => (),
+            }
+        }
+
+        if json_q > text_q {
+            Representation::Json
+        } else {
+            Representation::Text
+        }
+    }
+}
+
+/// Responds with `value` as JSON or with `text(value)` as plain text, depending on what the client
+/// accepts. Sets `Vary: Accept` so caches keep the representations apart.
+pub fn negotiated_response<T: Serialize>(
+    accept: Option<&str>,
+    params: &SupplyParams,
+    value: &T,
+    text: impl FnOnce(&T) -> String,
+) -> Response {
+    let mut response = match Representation::negotiate(accept, params) {
+        Representation::Json => json_response(value),
+        Representation::Text => text_response(text(value)),
+    };
+    response
+        .headers_mut()
+        .insert(header::VARY, HeaderValue::from_static("accept"));
+
+    response
+}
+
+fn accept(headers: &HeaderMap) -> Option<&str> {
+    headers.get(header::ACCEPT).and_then(|v| v.to_str().ok())
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct CirculatingSupplyResponse {
-    pub circulating_supply: u64,
+    /// Circulating supply in SOMM as a decimal string, so no precision is lost
+    pub circulating_supply: String,
+    pub circulating_supply_usomm: u64,
+    /// Unix timestamp of the oldest balance used in the calculation, if every balance has been
+    /// refreshed since startup
+    pub updated_at: Option<u64>,
+    /// Whether any balance used in the calculation is overdue for a refresh
+    pub stale: bool,
+    /// Lowest block height at which the balances used in the calculation were observed, if every
+    /// balance has a recorded height
+    pub observed_at_height: Option<u64>,
 }
 
 /// Calculates and returns the circulating supply. If one or more balance is not populated in the cache,
 /// returns a 503 status code.
 /// Circulating supply == Total supply - Foundation wallet - Staking - Community Pool - Vesting balances
-pub async fn get_circulating_supply() -> Response {
-    let balances = BALANCES.lock().await;
+///
+/// The body is the SOMM figure as plain text unless JSON is negotiated through the `Accept`
+/// header or `?format=json`, in which case a [`CirculatingSupplyResponse`] is returned.
+#[utoipa::path(
+    get,
+    path = "/v1/circulating-supply",
+    params(("format" = Option<String>, Query, description = "`json` or `text`, overriding the Accept header")),
+    responses(
+        (status = 200, description = "Amount in SOMM", content(
+            ("text/plain" = String),
+            ("application/json" = CirculatingSupplyResponse)
+        )),
+        (status = 503, description = "Balance not yet cached", body = ErrorResponse),
+    )
+)]
+pub async fn get_circulating_supply(
+    State(config): State<Arc<SommStatsConfig>>,
+    Query(params): Query<SupplyParams>,
+    headers: HeaderMap,
+) -> Result<Response, SommStatsError> {
+    let state = ChainState::of(&config);
+    let balances = state.balances.read().await;
+    let circulating_supply_usomm =
+        calculate_circulating_supply(&config, &balances).inspect_err(|e| match e {
+            SupplyError::MissingBalance(unpopulated) => warn!(
+                "circulating supply request failed due to missing balance for {}",
+                unpopulated
+            ),
+            SupplyError::ExceedsTotal(components) => error!(
+                "cached balances exceed the total supply of {}usomm: {:?}",
+                config.accounting.total_supply(),
+                components
+            ),
+        })?;
+    drop(balances);
+
+    let circulating_supply = format_decimal(circulating_supply_usomm, config.grpc.decimals);
+
+    let (updated_at, stale) =
+        supply_freshness(&config, &*state.updated_at.read().await, SystemTime::now());
+    if stale && !config.cache.serve_stale_on_failure {
+        warn!("circulating supply request failed due to stale balances");
+        return Err(SommStatsError::CacheStale("circulating supply".to_string()));
+    }
+    if let Some(max_age) = config.cache.max_balance_age_secs {
+        if exceeds_max_age(updated_at, max_age, SystemTime::now()) {
+            warn!(
+                "circulating supply request failed due to balances older than {}s",
+                max_age
+            );
+            return Err(SommStatsError::CacheStale("circulating supply".to_string()));
+        }
+    }
+    let response = CirculatingSupplyResponse {
+        circulating_supply,
+        circulating_supply_usomm,
+        updated_at: updated_at.map(unix_secs),
+        stale,
+        observed_at_height: observed_at_height(&config, &*state.heights.read().await),
+    };
+
+    Ok(negotiated_response(
+        accept(&headers),
+        &params,
+        &response,
+        |r| r.circulating_supply.clone(),
+    ))
+}
+
+/// Time range of the circulating supply history to return, as Unix timestamps
+#[derive(Debug, Default, Deserialize)]
+pub struct HistoryParams {
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+}
+
+/// Returns the recorded circulating supply samples, oldest first. A sample is recorded each time
+/// the cache snapshot is taken, and only the latest `supply_history_len` are kept.
+#[utoipa::path(
+    get,
+    path = "/v1/circulating-supply/history",
+    params(
+        ("from" = Option<u64>, Query, description = "Only samples taken at or after this Unix timestamp"),
+        ("to" = Option<u64>, Query, description = "Only samples taken at or before this Unix timestamp"),
+    ),
+    responses((status = 200, body = [SupplySample]))
+)]
+pub async fn get_circulating_supply_history(Query(params): Query<HistoryParams>) -> Response {
+    json_response(&supply_history(params.from, params.to).await)
+}
+
+/// Formats a base denom amount in the display denom as a decimal string without trailing zeros,
+/// e.g. 1_500_000 with 6 decimals is "1.5"
+pub fn format_decimal(amount: u64, decimals: u32) -> String {
+    let divisor = 10_u64.pow(decimals);
+    let whole = amount / divisor;
+    let fractional = amount % divisor;
+    if fractional == 0 {
+        return whole.to_string();
+    }
+
+    let fractional = format!("{:0width$}", fractional, width = decimals as usize);
+    format!("{}.{}", whole, fractional.trim_end_matches('0'))
+}
+
+/// Keys of every balance subtracted from the total supply to get the circulating supply. Vesting
+/// accounts listed in `exclude_from_circulating` are left out.
+pub fn required_balance_keys(config: &SommStatsConfig) -> Vec<String> {
+    let mut keys = vec![COMMUNITY_POOL_KEY.to_string()];
+    keys.extend(config.accounting.foundation_accounts());
+    keys.extend(config.accounting.subtracted_vesting_accounts());
+
+    keys
+}
+
+/// Seconds between refreshes of the balance stored under `key`
+pub fn expected_update_period(config: &SommStatsConfig, key: &str) -> u64 {
+    if key == COMMUNITY_POOL_KEY {
+        config.cache.community_pool_update_period
+    } else if key == BONDED_KEY {
+        config.cache.staking_update_period
+    } else if config
+        .accounting
+        .vesting_accounts()
+        .iter()
+        .any(|a| a == key)
+    {
+        config.cache.vesting_update_period
+    } else {
+        config.cache.foundation_wallet_update_period
+    }
+}
+
+/// Returns the last update time of the oldest balance needed for the circulating supply, and
+/// whether any of those balances is stale. A balance is stale if it hasn't been refreshed within
+/// twice its update period, which leaves room for a slow or retried poll, or if it has never been
+/// refreshed since startup.
+pub fn supply_freshness(
+    config: &SommStatsConfig,
+    updated_at: &HashMap<String, SystemTime>,
+    now: SystemTime,
+) -> (Option<SystemTime>, bool) {
+    let mut oldest: Option<SystemTime> = None;
+    let mut stale = false;
+    for key in required_balance_keys(config) {
+        let Some(&t) = updated_at.get(&key) else {
+            return (None, true);
+        };
+        let age = now.duration_since(t).unwrap_or_default();
+        if age.as_secs() > 2 * expected_update_period(config, &key) {
+            stale = true;
+        }
+        oldest = Some(oldest.map_or(t, |o| o.min(t)));
+    }
+
+    (oldest, stale)
+}
+
+/// Whether the oldest balance, last updated at `oldest`, is more than `max_age` seconds old. A
+/// balance that hasn't been updated since startup has an unknown age and counts as too old.
+pub fn exceeds_max_age(oldest: Option<SystemTime>, max_age: u64, now: SystemTime) -> bool {
+    oldest.is_none_or(|t| now.duration_since(t).unwrap_or_default() > Duration::from_secs(max_age))
+}
+
+/// Returns the lowest block height at which the balances needed for the circulating supply were
+/// observed, or `None` if any of them has no recorded height
+pub fn observed_at_height(config: &SommStatsConfig, heights: &HashMap<String, u64>) -> Option<u64> {
+    required_balance_keys(config)
+        .iter()
+        .map(|k| heights.get(k).copied())
+        .try_fold(u64::MAX, |min, h| h.map(|h| min.min(h)))
+}
+
+fn unix_secs(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Reasons the circulating supply can't be calculated from the cached balances
+#[derive(Debug, Eq, PartialEq)]
+pub enum SupplyError {
+    /// Key of the first balance missing from the cache
+    MissingBalance(String),
+    /// The balances sum to more than the total supply. Holds every component so the bad data can
+    /// be logged.
+    ExceedsTotal(Vec<(String, u64)>),
+}
+
+/// Calculates the circulating supply in usomm from the cached balances
+pub fn calculate_circulating_supply(
+    config: &SommStatsConfig,
+    balances: &HashMap<String, u64>,
+) -> std::result::Result<u64, SupplyError> {
     // instead of just summing all entries we get them individually to make sure none are missing,
     // which would make our calculation overshoot the actual circulating supply.
-    let mut less = vec![
-        (FOUNDATION_ADDRESS, balances.get(FOUNDATION_ADDRESS)),
-        (FOUNDATION_ADDRESS_2, balances.get(FOUNDATION_ADDRESS_2)),
-        (COMMUNITY_POOL_KEY, balances.get(COMMUNITY_POOL_KEY)),
-    ];
-    VESTING_ACCOUNTS
-        .iter()
-        .for_each(|v| less.push((v, balances.get(*v))));
+    let less = required_balance_keys(config)
+        .into_iter()
+        .map(|k| {
+            let balance = balances.get(&k).copied();
+            (k, balance)
+        })
+        .collect::<Vec<(String, Option<u64>)>>();
 
     if let Some(unpopulated) = less.iter().find(|v| v.1.is_none()) {
-        warn!(
-            "circulating supply request failed due to missing balance for {}",
-            unpopulated.0
-        );
-        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+        return Err(SupplyError::MissingBalance(unpopulated.0.clone()));
     }
 
-    let circulating_supply = TOTAL_USOMM_SUPPLY - less.iter().map(|v| v.1.unwrap()).sum::<u64>();
+    let less: Vec<(String, u64)> = less.into_iter().map(|(k, v)| (k, v.unwrap())).collect();
+    less.iter()
+        .try_fold(0_u64, |sum, (_, v)| sum.checked_add(*v))
+        .and_then(|sum| config.accounting.total_supply().checked_sub(sum))
+        .ok_or(SupplyError::ExceedsTotal(less))
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct TotalSupplyResponse {
+    /// Total supply in SOMM as a decimal string
+    pub total_supply: String,
+    pub total_supply_usomm: u64,
+}
+
+/// Returns the total supply in SOMM as plain text, or as a [`TotalSupplyResponse`] when JSON is
+/// negotiated.
+#[utoipa::path(
+    get,
+    path = "/v1/total-supply",
+    params(("format" = Option<String>, Query, description = "`json` or `text`, overriding the Accept header")),
+    responses(
+        (status = 200, description = "Amount in SOMM", content(
+            ("text/plain" = String),
+            ("application/json" = TotalSupplyResponse)
+        )),
+    )
+)]
+pub async fn get_total_supply(
+    State(config): State<Arc<SommStatsConfig>>,
+    Query(params): Query<SupplyParams>,
+    headers: HeaderMap,
+) -> Response {
+    let total_supply_usomm = config.accounting.total_supply();
+    let response = TotalSupplyResponse {
+        total_supply: format_decimal(total_supply_usomm, config.grpc.decimals),
+        total_supply_usomm,
+    };
+
+    negotiated_response(accept(&headers), &params, &response, |r| {
+        r.total_supply.clone()
+    })
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct StakedSupplyResponse {
+    /// Staked supply in SOMM as a decimal string
+    pub staked_supply: String,
+    pub staked_supply_usomm: u64,
+    /// Staked supply as a proportion of total supply
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub staked_ratio: Option<f64>,
+}
+
+/// Returns the total bonded supply in SOMM as plain text, or as a [`StakedSupplyResponse`] when
+/// JSON is negotiated. If the bonded balance is not populated in the cache, returns a 503
+/// status code.
+#[utoipa::path(
+    get,
+    path = "/v1/staked-supply",
+    params(("format" = Option<String>, Query, description = "`json` or `text`, overriding the Accept header")),
+    responses(
+        (status = 200, description = "Amount in SOMM", content(
+            ("text/plain" = String),
+            ("application/json" = StakedSupplyResponse)
+        )),
+        (status = 503, description = "Balance not yet cached", body = ErrorResponse),
+    )
+)]
+pub async fn get_staked_supply(
+    State(config): State<Arc<SommStatsConfig>>,
+    Query(params): Query<SupplyParams>,
+    headers: HeaderMap,
+) -> Result<Response, SommStatsError> {
+    let state = ChainState::of(&config);
+    let Some(staked_supply_usomm) = state.balances.read().await.get(BONDED_KEY).copied() else {
+        warn!("staked supply request failed due to missing bonded balance");
+        return Err(SommStatsError::CacheMiss("bonded balance".to_string()));
+    };
+    let staked_ratio = bonded_ratio(staked_supply_usomm, config.accounting.total_supply());
+    let response = StakedSupplyResponse {
+        staked_supply: format_decimal(staked_supply_usomm, config.grpc.decimals),
+        staked_supply_usomm,
+        staked_ratio,
+    };
+
+    Ok(negotiated_response(
+        accept(&headers),
+        &params,
+        &response,
+        |r| r.staked_supply.clone(),
+    ))
+}
+
+/// Proportion of the total supply that is bonded, or `None` if the total supply is zero
+fn bonded_ratio(bonded_usomm: u64, total_supply_usomm: u64) -> Option<f64> {
+    (total_supply_usomm > 0).then(|| bonded_usomm as f64 / total_supply_usomm as f64)
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct BondedRatioResponse {
+    /// Bonded supply as a proportion of total supply
+    pub bonded_ratio: f64,
+}
 
-    // convert to SOMM
-    let circulating_supply = circulating_supply / 1_000_000;
+/// Returns the bonded supply as a proportion of the total supply, to four decimal places as plain
+/// text, or as a [`BondedRatioResponse`] when JSON is negotiated. If the bonded balance is not
+/// populated in the cache, returns a 503 status code.
+#[utoipa::path(
+    get,
+    path = "/v1/bonded-ratio",
+    params(("format" = Option<String>, Query, description = "`json` or `text`, overriding the Accept header")),
+    responses(
+        (status = 200, description = "Bonded proportion of total supply", content(
+            ("text/plain" = String),
+            ("application/json" = BondedRatioResponse)
+        )),
+        (status = 503, description = "Balance not yet cached", body = ErrorResponse),
+    )
+)]
+pub async fn get_bonded_ratio(
+    State(config): State<Arc<SommStatsConfig>>,
+    Query(params): Query<SupplyParams>,
+    headers: HeaderMap,
+) -> Result<Response, SommStatsError> {
+    let state = ChainState::of(&config);
+    let Some(bonded_usomm) = state.balances.read().await.get(BONDED_KEY).copied() else {
+        warn!("bonded ratio request failed due to missing bonded balance");
+        return Err(SommStatsError::CacheMiss("bonded balance".to_string()));
+    };
+    // the total supply comes from the config, which can't set it to 0
+    let Some(bonded_ratio) = bonded_ratio(bonded_usomm, config.accounting.total_supply()) else {
+        error!("bonded ratio request failed due to a total supply of 0");
+        return Err(SommStatsError::Internal("total supply is 0".to_string()));
+    };
 
-    response(circulating_supply.to_string())
+    Ok(negotiated_response(
+        accept(&headers),
+        &params,
+        &BondedRatioResponse { bonded_ratio },
+        |r| format!("{:.4}", r.bonded_ratio),
+    ))
 }
 
-pub fn response(body: String) -> Response {
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct CommunityPoolResponse {
+    /// Community pool balance in SOMM as a decimal string
+    pub community_pool: String,
+    pub community_pool_usomm: u64,
+}
+
+/// Returns the cached community pool balance in SOMM as plain text, or as a
+/// [`CommunityPoolResponse`] when JSON is negotiated. If the balance is not populated in
+/// the cache, returns a 503 status code.
+#[utoipa::path(
+    get,
+    path = "/v1/community-pool",
+    params(("format" = Option<String>, Query, description = "`json` or `text`, overriding the Accept header")),
+    responses(
+        (status = 200, description = "Amount in SOMM", content(
+            ("text/plain" = String),
+            ("application/json" = CommunityPoolResponse)
+        )),
+        (status = 503, description = "Balance not yet cached", body = ErrorResponse),
+    )
+)]
+pub async fn get_community_pool(
+    State(config): State<Arc<SommStatsConfig>>,
+    Query(params): Query<SupplyParams>,
+    headers: HeaderMap,
+) -> Result<Response, SommStatsError> {
+    let state = ChainState::of(&config);
+    let Some(community_pool_usomm) = state.balances.read().await.get(COMMUNITY_POOL_KEY).copied()
+    else {
+        warn!("community pool request failed due to missing community pool balance");
+        return Err(SommStatsError::CacheMiss(
+            "community pool balance".to_string(),
+        ));
+    };
+    let response = CommunityPoolResponse {
+        community_pool: format_decimal(community_pool_usomm, config.grpc.decimals),
+        community_pool_usomm,
+    };
+
+    Ok(negotiated_response(
+        accept(&headers),
+        &params,
+        &response,
+        |r| r.community_pool.clone(),
+    ))
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct AccountBalanceResponse {
+    pub address: String,
+    /// Balance in SOMM as a decimal string
+    pub balance: String,
+    pub balance_usomm: u64,
+}
+
+/// Returns the balance of any Sommelier address as an [`AccountBalanceResponse`]. Balances are
+/// cached for `balance_lookup_ttl` seconds. Malformed addresses get a 400, and a 503 is returned
+/// if the balance can't be queried.
+#[utoipa::path(
+    get,
+    path = "/v1/balance/{address}",
+    params(("address" = String, Path, description = "Bech32 somm address")),
+    responses(
+        (status = 200, body = AccountBalanceResponse),
+        (status = 400, description = "Malformed address", body = ErrorResponse),
+        (status = 503, description = "Balance couldn't be queried", body = ErrorResponse),
+    )
+)]
+pub async fn get_account_balance(
+    State(config): State<Arc<SommStatsConfig>>,
+    Path(address): Path<String>,
+) -> Result<Response, SommStatsError> {
+    let balance_usomm = lookup_account_balance(&config, &address).await?;
+
+    Ok(json_response(&AccountBalanceResponse {
+        address,
+        balance: format_decimal(balance_usomm, config.grpc.decimals),
+        balance_usomm,
+    }))
+}
+
+/// Addresses looked up concurrently by a single `/v1/balances` request
+const BULK_BALANCE_CONCURRENCY: usize = 8;
+
+/// Balances in usomm of the addresses that could be looked up, and the error for each that
+/// couldn't
+#[derive(Debug, Default, Deserialize, Serialize, ToSchema)]
+pub struct BulkBalancesResponse {
+    pub balances: BTreeMap<String, u64>,
+    pub errors: BTreeMap<String, ErrorBody>,
+}
+
+/// Looks up the balances of a JSON array of Sommelier addresses, a few at a time. Results are
+/// cached like `/v1/balance/{address}`. An address that is malformed or can't be queried is
+/// reported under `errors` without failing the rest of the batch. Requests with more than
+/// `max_bulk_addresses` addresses get a 400.
+#[utoipa::path(
+    post,
+    path = "/v1/balances",
+    request_body = Vec<String>,
+    responses(
+        (status = 200, body = BulkBalancesResponse),
+        (status = 400, description = "Too many addresses", body = ErrorResponse),
+    )
+)]
+pub async fn post_balances(
+    State(config): State<Arc<SommStatsConfig>>,
+    Json(addresses): Json<Vec<String>>,
+) -> Result<Response, SommStatsError> {
+    let addresses: BTreeSet<String> = addresses.into_iter().collect();
+    if addresses.len() > config.server.max_bulk_addresses {
+        return Err(SommStatsError::ParseError(format!(
+            "at most {} addresses may be looked up at once",
+            config.server.max_bulk_addresses
+        )));
+    }
+
+    let results: Vec<(String, Result<u64, SommStatsError>)> = stream::iter(addresses)
+        .map(|address| {
+            let config = &config;
+            async move {
+                let result = lookup_account_balance(config, &address).await;
+                (address, result)
+            }
+        })
+        .buffer_unordered(BULK_BALANCE_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut response = BulkBalancesResponse::default();
+    for (address, result) in results {
+        match result {
+            Ok(balance) => {
+                response.balances.insert(address, balance);
+            }
+            Err(e) => {
+                let body = ErrorBody {
+                    code: e.code().to_string(),
+                    message: e.to_string(),
+                };
+                response.errors.insert(address, body);
+            }
+        }
+    }
+
+    Ok(json_response(&response))
+}
+
+/// Validates a Sommelier address and looks up its balance, caching it for `balance_lookup_ttl`
+/// seconds
+async fn lookup_account_balance(
+    config: &SommStatsConfig,
+    address: &str,
+) -> Result<u64, SommStatsError> {
+    match address.parse::<AccountId>() {
+        Ok(id) if id.prefix() == ACCOUNT_PREFIX => (),
+        _ => return Err(SommStatsError::InvalidAddress(address.to_string())),
+    }
+
+    let ttl = Duration::from_secs(config.cache.balance_lookup_ttl);
+    lookup_balance(&GrpcSource, &config.grpc, ttl, address)
+        .await
+        .map_err(|e| {
+            warn!("balance lookup failed: {:?}", e);
+            if config.grpc.endpoints.is_empty() {
+                SommStatsError::NoGrpcEndpoints
+            } else {
+                SommStatsError::GrpcUnavailable(
+                    "balance couldn't be queried from any endpoint".to_string(),
+                )
+            }
+        })
+}
+
+/// Every component of the circulating supply calculation in usomm. Components missing from the
+/// cache are `null`. Vesting accounts listed in `exclude_from_circulating` are left out.
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct SupplyBreakdownResponse {
+    pub total_supply: u64,
+    /// Foundation balances keyed by address, replacing the former `foundation` and
+    /// `foundation_2` fields
+    pub foundation: BTreeMap<String, Option<u64>>,
+    pub community_pool: Option<u64>,
+    pub vesting: BTreeMap<String, Option<u64>>,
+    pub circulating_supply: Option<u64>,
+    pub complete: bool,
+}
+
+/// Block height to query the supply breakdown at
+#[derive(Debug, Default, Deserialize)]
+pub struct BreakdownParams {
+    pub height: Option<u64>,
+}
+
+/// Returns each balance subtracted from the total supply along with the derived circulating
+/// supply. Unlike [`get_circulating_supply`] this does not 503 when balances are missing, it
+/// returns the partial data with `complete` set to false so stale components can be identified.
+///
+/// With `?height=` every balance is queried from the chain as of that block height instead of read
+/// from the cache, for reconciling against explorers. Vesting balances are calculated as of the
+/// block's time. If any of the queries fail, e.g. because every endpoint has pruned the height,
+/// the response is a 503. Heights are only accepted when `historical_queries` is enabled.
+#[utoipa::path(
+    get,
+    path = "/v1/supply-breakdown",
+    params(
+        ("height" = Option<u64>, Query, description = "Query the balances at this block height instead of the latest cached balances"),
+    ),
+    responses(
+        (status = 200, body = SupplyBreakdownResponse),
+        (status = 400, description = "Historical queries are disabled", body = ErrorResponse),
+        (status = 503, description = "Balances couldn't be queried at the height", body = ErrorResponse),
+    )
+)]
+pub async fn get_supply_breakdown(
+    State(config): State<Arc<SommStatsConfig>>,
+    Query(params): Query<BreakdownParams>,
+) -> Result<Response, SommStatsError> {
+    if let Some(height) = params.height {
+        if !config.server.historical_queries {
+            return Err(SommStatsError::ParseError(
+                "height queries are disabled on this server".to_string(),
+            ));
+        }
+        let balances = query_balances_at_height(&GrpcSource, &config, height)
+            .await
+            .map_err(|e| {
+                warn!("supply breakdown at height {} failed: {:?}", height, e);
+                if config.grpc.endpoints.is_empty() {
+                    SommStatsError::NoGrpcEndpoints
+                } else {
+                    SommStatsError::GrpcUnavailable(format!(
+                        "balances couldn't be queried at height {height} from any endpoint"
+                    ))
+                }
+            })?;
+
+        return Ok(json_response(&supply_breakdown(&config, &balances)));
+    }
+
+    let state = ChainState::of(&config);
+    let balances = state.balances.read().await;
+    let breakdown = supply_breakdown(&config, &balances);
+    drop(balances);
+
+    Ok(json_response(&breakdown))
+}
+
+/// Builds the supply breakdown from the given balances
+pub fn supply_breakdown(
+    config: &SommStatsConfig,
+    balances: &HashMap<String, u64>,
+) -> SupplyBreakdownResponse {
+    let foundation: BTreeMap<String, Option<u64>> = config
+        .accounting
+        .foundation_accounts()
+        .into_iter()
+        .map(|a| {
+            let balance = balances.get(&a).copied();
+            (a, balance)
+        })
+        .collect();
+    let community_pool = balances.get(COMMUNITY_POOL_KEY).copied();
+    let vesting: BTreeMap<String, Option<u64>> = config
+        .accounting
+        .subtracted_vesting_accounts()
+        .into_iter()
+        .map(|a| {
+            let balance = balances.get(&a).copied();
+            (a, balance)
+        })
+        .collect();
+    // null if a balance is missing or the balances exceed the total supply
+    let circulating_supply = calculate_circulating_supply(config, balances).ok();
+
+    let complete = community_pool.is_some()
+        && foundation.values().all(|v| v.is_some())
+        && vesting.values().all(|v| v.is_some());
+
+    SupplyBreakdownResponse {
+        total_supply: config.accounting.total_supply(),
+        foundation,
+        community_pool,
+        vesting,
+        circulating_supply,
+        complete,
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct VestingAccountBalance {
+    pub address: String,
+    pub locked_usomm: u64,
+    /// Locked balance in SOMM as a decimal string
+    pub locked_somm: String,
+}
+
+/// Locked balances of the vesting accounts subtracted from the total supply
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct VestingResponse {
+    /// Vesting accounts whose locked balance is cached
+    pub accounts: Vec<VestingAccountBalance>,
+    /// Total locked balance in whole SOMM, rounded down
+    pub total_locked: u64,
+    pub total_locked_usomm: u64,
+    /// Total locked balance in SOMM as a decimal string
+    pub total_locked_somm: String,
+    /// Whether every subtracted vesting account is cached
+    pub complete: bool,
+}
+
+/// Returns the cached locked balance of each vesting account along with their total. Accounts
+/// missing from the cache are left out and `complete` is set to false.
+#[utoipa::path(
+    get,
+    path = "/v1/vesting",
+    responses((status = 200, body = VestingResponse))
+)]
+pub async fn get_vesting(State(config): State<Arc<SommStatsConfig>>) -> Response {
+    let divisor = config.grpc.display_divisor();
+    let state = ChainState::of(&config);
+    let balances = state.balances.read().await;
+    let vesting_accounts = config.accounting.subtracted_vesting_accounts();
+    let accounts: Vec<VestingAccountBalance> = vesting_accounts
+        .iter()
+        .filter_map(|a| {
+            balances.get(a).map(|&locked_usomm| VestingAccountBalance {
+                address: a.clone(),
+                locked_usomm,
+                locked_somm: format_decimal(locked_usomm, config.grpc.decimals),
+            })
+        })
+        .collect();
+    drop(balances);
+
+    let total_locked_usomm = accounts.iter().map(|a| a.locked_usomm).sum();
+    let complete = accounts.len() == vesting_accounts.len();
+
+    json_response(&VestingResponse {
+        accounts,
+        total_locked: total_locked_usomm / divisor,
+        total_locked_usomm,
+        total_locked_somm: format_decimal(total_locked_usomm, config.grpc.decimals),
+        complete,
+    })
+}
+
+/// Returns the health of each configured gRPC endpoint as tracked by the pollers
+#[utoipa::path(
+    get,
+    path = "/v1/endpoints/health",
+    responses((status = 200, body = [EndpointHealthReport]))
+)]
+pub async fn get_endpoints_health(State(config): State<Arc<SommStatsConfig>>) -> Response {
+    let report = ENDPOINT_HEALTH.read().await.report(&config.grpc.endpoints);
+
+    json_response(&report)
+}
+
+/// Seconds since each cached balance was last refreshed from chain, keyed like the balances cache.
+/// Balances needed for the circulating supply that haven't been refreshed since startup are
+/// `null`.
+#[utoipa::path(
+    get,
+    path = "/v1/supply-freshness",
+    responses((status = 200, description = "Age in seconds of each cached balance, keyed like the balances cache", body = Object))
+)]
+pub async fn get_supply_freshness(State(config): State<Arc<SommStatsConfig>>) -> Response {
+    let now = SystemTime::now();
+    let state = ChainState::of(&config);
+    let updated_at = state.updated_at.read().await;
+    let mut ages: BTreeMap<String, Option<u64>> = required_balance_keys(&config)
+        .into_iter()
+        .map(|k| (k, None))
+        .collect();
+    for (key, t) in updated_at.iter() {
+        let age = now.duration_since(*t).unwrap_or_default().as_secs();
+        ages.insert(key.clone(), Some(age));
+    }
+
+    json_response(&ages)
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct ReadinessResponse {
+    pub ready: bool,
+    pub missing: Vec<String>,
+}
+
+/// Readiness probe. Returns 200 once every balance needed to calculate the circulating supply of
+/// every chain is cached, otherwise 503 along with the keys that are still missing. Keys of chains
+/// under `[chains]` are prefixed with the chain name, e.g. `hub/communitypool`.
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    responses(
+        (status = 200, body = ReadinessResponse),
+        (status = 503, body = ReadinessResponse),
+    )
+)]
+pub async fn get_readiness(State(config): State<Arc<SommStatsConfig>>) -> Response {
+    let mut missing = missing_balance_keys(&config).await;
+    for name in config.chains.keys() {
+        if let Some(chain) = config.chain(name) {
+            let keys = missing_balance_keys(&chain).await;
+            missing.extend(keys.into_iter().map(|k| format!("{name}/{k}")));
+        }
+    }
+
+    let ready = missing.is_empty();
+    let mut response = json_response(&ReadinessResponse { ready, missing });
+    if !ready {
+        *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+    }
+
+    response
+}
+
+/// Keys of the balances needed for the circulating supply of the chain `config` is for that
+/// aren't cached yet
+async fn missing_balance_keys(config: &SommStatsConfig) -> Vec<String> {
+    let state = ChainState::of(config);
+    let balances = state.balances.read().await;
+
+    required_balance_keys(config)
+        .into_iter()
+        .filter(|k| !balances.contains_key(k))
+        .collect()
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct VersionResponse {
+    pub version: String,
+    /// Commit the binary was built from, or `unknown` if it wasn't built from a git checkout
+    pub git_commit: String,
+    /// Unix time the binary was built
+    pub build_timestamp: u64,
+}
+
+/// Returns the version, git commit and build time of the running binary
+#[utoipa::path(get, path = "/version", responses((status = 200, body = VersionResponse)))]
+pub async fn get_version() -> Response {
+    json_response(&VersionResponse {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_commit: env!("SOMMSTATS_GIT_COMMIT").to_string(),
+        build_timestamp: env!("SOMMSTATS_BUILD_TIMESTAMP")
+            .parse()
+            .unwrap_or_default(),
+    })
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct HealthzResponse {
+    pub healthy: bool,
+    pub reachable: Vec<String>,
+    pub unreachable: Vec<String>,
+}
+
+/// Connectivity check. Queries the latest block from every configured gRPC endpoint and returns
+/// 200 if at least one responds within the query timeout, otherwise 503 along with the endpoints
+/// that couldn't be reached. The probe is reused for a few seconds, so frequent checks don't each
+/// query every endpoint.
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    responses(
+        (status = 200, body = HealthzResponse),
+        (status = 503, body = HealthzResponse),
+    )
+)]
+pub async fn get_healthz(State(config): State<Arc<SommStatsConfig>>) -> Response {
+    let (reachable, unreachable) = cached_probe_endpoints(&config.grpc).await;
+    let healthy = !reachable.is_empty();
+    if !healthy {
+        warn!("no gRPC endpoint is reachable: {:?}", unreachable);
+    }
+
+    let mut response = json_response(&HealthzResponse {
+        healthy,
+        reachable,
+        unreachable,
+    });
+    if !healthy {
+        *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+    }
+
+    response
+}
+
+/// A cached balance along with how long ago it was refreshed
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CachedBalance {
+    pub value: u64,
+    /// `None` if the balance hasn't been refreshed since startup, e.g. it came from a snapshot
+    pub age_secs: Option<u64>,
+    /// Whether the balance hasn't been refreshed within twice its update period
+    pub stale: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DebugCachesResponse {
+    pub balances: BTreeMap<String, CachedBalance>,
+    pub lookup_balances: usize,
+    pub expired_lookup_balances: usize,
+    pub grpc_channels: Vec<String>,
+}
+
+/// Dumps the contents of the in-memory caches for troubleshooting. Only routed when
+/// `server.debug_endpoints` is enabled.
+pub async fn get_debug_caches(State(config): State<Arc<SommStatsConfig>>) -> Response {
+    let now = SystemTime::now();
+    let updated_at = BALANCES_UPDATED_AT.read().await;
+    let balances = BALANCES
+        .read()
+        .await
+        .iter()
+        .map(|(key, value)| {
+            let age_secs = updated_at
+                .get(key)
+                .map(|t| now.duration_since(*t).unwrap_or_default().as_secs());
+            let stale = age_secs.is_none_or(|age| age > 2 * expected_update_period(&config, key));
+
+            (
+                key.clone(),
+                CachedBalance {
+                    value: *value,
+                    age_secs,
+                    stale,
+                },
+            )
+        })
+        .collect();
+    drop(updated_at);
+
+    let ttl = Duration::from_secs(config.cache.balance_lookup_ttl);
+    let lookup_balances = LOOKUP_BALANCES.read().await;
+    let expired_lookup_balances = lookup_balances
+        .values()
+        .filter(|(_, queried_at)| queried_at.elapsed() >= ttl)
+        .count();
+
+    let mut grpc_channels: Vec<String> = CHANNELS.read().await.keys().cloned().collect();
+    grpc_channels.sort();
+
+    json_response(&DebugCachesResponse {
+        balances,
+        lookup_balances: lookup_balances.len(),
+        expired_lookup_balances,
+        grpc_channels,
+    })
+}
+
+/// Serves the OpenAPI document describing the API. When `public_base_url` is configured it is
+/// listed as the server, so that clients resolve the paths against the externally visible URL.
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    responses((status = 200, description = "OpenAPI 3 document", content_type = "application/json"))
+)]
+pub async fn get_openapi(State(config): State<Arc<SommStatsConfig>>) -> Response {
+    let mut doc = ApiDoc::openapi();
+    if let Some(url) = &config.server.public_base_url {
+        doc.servers = Some(vec![utoipa::openapi::Server::new(
+            url.trim_end_matches('/'),
+        )]);
+    }
+
+    json_response(&doc)
+}
+
+/// Caches that can be refreshed through `/admin/refresh/:cache`
+const REFRESHABLE_CACHES: &[&str] = &["balances"];
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct RefreshResponse {
+    pub cache: String,
+    /// Keys that were refreshed
+    pub refreshed: Vec<String>,
+    /// Keys whose refresh failed, which keep their previously cached value
+    pub errors: BTreeMap<String, ErrorBody>,
+}
+
+/// Refreshes a cache immediately instead of waiting for the next poll cycle. Each entry is queried
+/// once without retries. Only routed when `server.admin_token` is set, and requires it as a bearer
+/// token.
+pub async fn post_admin_refresh(
+    State(config): State<Arc<SommStatsConfig>>,
+    Path(cache): Path<String>,
+) -> Result<Response, SommStatsError> {
+    if !REFRESHABLE_CACHES.contains(&cache.as_str()) {
+        return Err(SommStatsError::NotFound(format!(
+            "unknown cache {cache:?}, expected one of: {}",
+            REFRESHABLE_CACHES.join(", ")
+        )));
+    }
+
+    info!("refreshing {} on admin request", cache);
+    let mut response = RefreshResponse {
+        cache,
+        ..Default::default()
+    };
+    for (key, result) in refresh_balances(&GrpcSource, &config).await {
+        match result {
+            Ok(()) => response.refreshed.push(key),
+            Err(e) => {
+                warn!("admin refresh of {} failed: {:?}", key, e);
+                let body = ErrorBody {
+                    code: QUERY_FAILED.to_string(),
+                    message: e.to_string(),
+                };
+                response.errors.insert(key, body);
+            }
+        }
+    }
+    response.refreshed.sort();
+
+    Ok(json_response(&response))
+}
+
+/// Renders the Prometheus metrics. The circulating supply gauge is refreshed from the cache first
+/// so it reflects the latest balances even if the supply endpoint hasn't been hit.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Prometheus text exposition format", body = String, content_type = "text/plain"),
+        (status = 503, description = "Metrics recorder not installed", body = ErrorResponse),
+    )
+)]
+pub async fn get_metrics(
+    State(config): State<Arc<SommStatsConfig>>,
+) -> Result<Response, SommStatsError> {
+    if let Ok(supply) = calculate_circulating_supply(&config, &*BALANCES.read().await) {
+        gauge!(CIRCULATING_SUPPLY, supply as f64);
+    }
+
+    telemetry::render()
+        .map(text_response)
+        .ok_or(SommStatsError::MetricsUnavailable)
+}
+
+/// Body of every error response
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: ErrorBody,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct ErrorBody {
+    /// Stable machine-readable error code, e.g. `cache_incomplete`
+    pub code: String,
+    /// Human-readable description of the error
+    pub message: String,
+}
+
+/// Builds an error response with the given status and an [`ErrorResponse`] body
+pub fn error_response(status: StatusCode, code: &str, message: impl Into<String>) -> Response {
+    let body = ErrorResponse {
+        error: ErrorBody {
+            code: code.to_string(),
+            message: message.into(),
+        },
+    };
+
+    (status, Json(body)).into_response()
+}
+
+/// Reported when a response body can't be serialized or the response can't be built. The details
+/// are logged rather than returned to the client.
+fn internal_error() -> Response {
+    SommStatsError::Internal("error building response".to_string()).into_response()
+}
+
+pub fn text_response(body: String) -> Response {
     Response::builder()
         .header("Content-Type", "text/plain")
         .body(body)
-        .map_err(|e| {
+        .map(IntoResponse::into_response)
+        .unwrap_or_else(|e| {
+            error!("error building response: {:?}", e);
+            internal_error()
+        })
+}
+
+pub fn json_response<T: Serialize>(body: &T) -> Response {
+    let body = match serde_json::to_string(body) {
+        Ok(b) => b,
+        Err(e) => {
+            error!("error serializing response body: {:?}", e);
+            return internal_error();
+        }
+    };
+
+    Response::builder()
+        .header("Content-Type", "application/json")
+        .body(body)
+        .map(IntoResponse::into_response)
+        .unwrap_or_else(|e| {
             error!("error building response: {:?}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+            internal_error()
         })
-        .into_response()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::accounting::{
+        FOUNDATION_ADDRESS, FOUNDATION_ADDRESS_2, TOTAL_USOMM_SUPPLY, VESTING_ACCOUNTS,
+    };
+    use crate::application::ENDPOINT_PROBE;
+    use crate::config::ChainSection;
+    use crate::error::{
+        CACHE_INCOMPLETE, CACHE_STALE, INTERNAL_ERROR, INVALID_ADDRESS, NOT_FOUND,
+        NO_GRPC_ENDPOINTS,
+    };
+    use crate::query::{
+        update_balance, update_community_pool_balance, update_foundation_balance,
+        update_vesting_balance, update_vesting_balances,
+    };
+    use crate::query::{EndpointProbe, PROBE_TTL_SECS};
+    use crate::source::MockSource;
     use assay::assay;
-    use axum::http::StatusCode;
+    use axum::{
+        body::Body,
+        http::{header, Request, StatusCode},
+    };
+    use std::net::Ipv4Addr;
+    use tower::ServiceExt;
+
+    fn config() -> State<Arc<SommStatsConfig>> {
+        State(Arc::new(SommStatsConfig::default()))
+    }
 
     #[assay]
     async fn get_circulating_supply_service_unavailable() {
         let expected = StatusCode::SERVICE_UNAVAILABLE;
-        let actual = get_circulating_supply().await;
+        let actual =
+            get_circulating_supply(config(), Query(SupplyParams::default()), HeaderMap::new())
+                .await
+                .into_response();
 
         assert_eq!(expected, actual.status());
     }
+
+    #[assay]
+    async fn get_circulating_supply_json() {
+        {
+            let mut balances = BALANCES.write().await;
+            balances.insert(FOUNDATION_ADDRESS.to_string(), 1_000_000);
+            balances.insert(FOUNDATION_ADDRESS_2.to_string(), 1_000_000);
+            balances.insert(COMMUNITY_POOL_KEY.to_string(), 1_000_000);
+            VESTING_ACCOUNTS.iter().for_each(|v| {
+                balances.insert(v.to_string(), 0);
+            });
+        }
+
+        let params = SupplyParams {
+            format: Some("json".to_string()),
+        };
+        let actual = get_circulating_supply(config(), Query(params), HeaderMap::new())
+            .await
+            .into_response();
+        assert_eq!(StatusCode::OK, actual.status());
+        assert_eq!(
+            "application/json",
+            actual.headers().get("Content-Type").unwrap()
+        );
+
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: CirculatingSupplyResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            TOTAL_USOMM_SUPPLY - 3_000_000,
+            body.circulating_supply_usomm
+        );
+        assert_eq!(
+            format_decimal(body.circulating_supply_usomm, 6),
+            body.circulating_supply
+        );
+    }
+
+    #[assay]
+    async fn get_circulating_supply_fresh_after_dropping_vested_account() {
+        let mut config = SommStatsConfig::default();
+        config.grpc.endpoints = vec!["http://endpoint0".to_string()];
+        config.accounting.foundation_addresses = vec!["somm1foundation".to_string()];
+        config.accounting.vesting_addresses = vec!["somm1vesting".to_string()];
+        config.cache.vesting_drop_threshold = 1;
+        config.cache.serve_stale_on_failure = false;
+        config.cache.max_balance_age_secs = Some(3600);
+        let source = MockSource {
+            balances: HashMap::from([("somm1vesting".to_string(), 0)]),
+            ..Default::default()
+        };
+        let mut zero_cycles = HashMap::new();
+        update_vesting_balances(&source, &config, &mut zero_cycles).await;
+        assert_eq!(Some(&1), zero_cycles.get("somm1vesting"));
+
+        // every balance was last updated long ago, as if several vesting periods have passed
+        let long_ago = SystemTime::now() - Duration::from_secs(3 * 3600);
+        for key in required_balance_keys(&config) {
+            BALANCES.write().await.entry(key.clone()).or_insert(1);
+            BALANCES_UPDATED_AT.write().await.insert(key, long_ago);
+        }
+        update_balance(&ChainState::get(None), "somm1foundation", 1).await;
+        update_balance(&ChainState::get(None), COMMUNITY_POOL_KEY, 1).await;
+
+        // the dropped account isn't queried, the mock would fail it if it were
+        let source = MockSource::default();
+        update_vesting_balances(&source, &config, &mut zero_cycles).await;
+
+        let actual = get_circulating_supply(
+            State(Arc::new(config)),
+            Query(SupplyParams::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .into_response();
+        assert_eq!(StatusCode::OK, actual.status());
+    }
+
+    #[assay]
+    async fn get_circulating_supply_enforces_max_balance_age() {
+        let mut config = SommStatsConfig::default();
+        config.accounting.foundation_addresses = vec!["somm1foundation".to_string()];
+        config.accounting.vesting_addresses = vec!["somm1vesting".to_string()];
+        {
+            let mut balances = BALANCES.write().await;
+            let mut updated_at = BALANCES_UPDATED_AT.write().await;
+            let half_hour_ago = SystemTime::now() - Duration::from_secs(1800);
+            for key in required_balance_keys(&config) {
+                balances.insert(key.clone(), 1);
+                updated_at.insert(key, half_hour_ago);
+            }
+        }
+        let get = |config: SommStatsConfig| async move {
+            get_circulating_supply(
+                State(Arc::new(config)),
+                Query(SupplyParams::default()),
+                HeaderMap::new(),
+            )
+            .await
+            .into_response()
+        };
+
+        assert_eq!(StatusCode::OK, get(config.clone()).await.status());
+
+        config.cache.max_balance_age_secs = Some(3600);
+        assert_eq!(StatusCode::OK, get(config.clone()).await.status());
+
+        config.cache.max_balance_age_secs = Some(600);
+        let actual = get(config).await;
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, actual.status());
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(CACHE_STALE, body.error.code);
+    }
+
+    #[assay]
+    async fn get_circulating_supply_stale_unless_serving_stale() {
+        let mut config = SommStatsConfig::default();
+        config.accounting.foundation_addresses = vec!["somm1foundation".to_string()];
+        config.accounting.vesting_addresses = vec!["somm1vesting".to_string()];
+        {
+            let mut balances = BALANCES.write().await;
+            let mut updated_at = BALANCES_UPDATED_AT.write().await;
+            let long_ago = SystemTime::now() - Duration::from_secs(3 * 3600);
+            for key in required_balance_keys(&config) {
+                balances.insert(key.clone(), 1);
+                updated_at.insert(key, long_ago);
+            }
+        }
+
+        let params = SupplyParams {
+            format: Some("json".to_string()),
+        };
+        let actual = get_circulating_supply(
+            State(Arc::new(config.clone())),
+            Query(params),
+            HeaderMap::new(),
+        )
+        .await
+        .into_response();
+        assert_eq!(StatusCode::OK, actual.status());
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: CirculatingSupplyResponse = serde_json::from_slice(&body).unwrap();
+        assert!(body.stale);
+        assert!(body.updated_at.is_some());
+
+        config.cache.serve_stale_on_failure = false;
+        let actual = get_circulating_supply(
+            State(Arc::new(config)),
+            Query(SupplyParams::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .into_response();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, actual.status());
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(CACHE_STALE, body.error.code);
+    }
+
+    #[test]
+    fn supply_freshness_reports_oldest_and_stale() {
+        let config = SommStatsConfig::default();
+        let now = SystemTime::now();
+        let mut updated_at: HashMap<String, SystemTime> = required_balance_keys(&config)
+            .into_iter()
+            .map(|k| (k, now))
+            .collect();
+
+        assert_eq!(
+            (Some(now), false),
+            supply_freshness(&config, &updated_at, now)
+        );
+
+        let old = now - Duration::from_secs(3 * config.cache.vesting_update_period);
+        updated_at.insert(VESTING_ACCOUNTS[0].to_string(), old);
+        assert_eq!(
+            (Some(old), true),
+            supply_freshness(&config, &updated_at, now)
+        );
+
+        updated_at.remove(COMMUNITY_POOL_KEY);
+        assert_eq!((None, true), supply_freshness(&config, &updated_at, now));
+    }
+
+    #[test]
+    fn observed_at_height_is_lowest_height() {
+        let config = SommStatsConfig::default();
+        let mut heights: HashMap<String, u64> = required_balance_keys(&config)
+            .into_iter()
+            .map(|k| (k, 100))
+            .collect();
+        heights.insert(COMMUNITY_POOL_KEY.to_string(), 90);
+        assert_eq!(Some(90), observed_at_height(&config, &heights));
+
+        heights.remove(FOUNDATION_ADDRESS);
+        assert_eq!(None, observed_at_height(&config, &heights));
+    }
+
+    async fn preflight(config: SommStatsConfig, origin: &str) -> Response {
+        let request = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/v1/circulating-supply")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+            .body(Body::empty())
+            .unwrap();
+
+        router(Arc::new(config))
+            .unwrap()
+            .oneshot(request)
+            .await
+            .unwrap()
+    }
+
+    #[assay]
+    async fn cors_preflight_allows_any_origin_by_default() {
+        let actual = preflight(SommStatsConfig::default(), "https://example.com").await;
+
+        assert_eq!(StatusCode::OK, actual.status());
+        assert_eq!(
+            "*",
+            actual
+                .headers()
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .unwrap()
+        );
+    }
+
+    #[assay]
+    async fn cors_preflight_restricts_configured_origins() {
+        let mut config = SommStatsConfig::default();
+        config.server.allowed_origins = vec!["https://somm.finance".to_string()];
+
+        let actual = preflight(config.clone(), "https://somm.finance").await;
+        assert_eq!(
+            "https://somm.finance",
+            actual
+                .headers()
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .unwrap()
+        );
+
+        let actual = preflight(config, "https://example.com").await;
+        assert!(actual
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+    }
+
+    async fn get_gzip(config: SommStatsConfig, uri: &str) -> Response {
+        let request = Request::builder()
+            .uri(uri)
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+
+        router(Arc::new(config))
+            .unwrap()
+            .oneshot(request)
+            .await
+            .unwrap()
+    }
+
+    #[assay]
+    async fn compression_honors_accept_encoding() {
+        let actual = get_gzip(SommStatsConfig::default(), "/readyz").await;
+        assert_eq!(
+            "gzip",
+            actual.headers().get(header::CONTENT_ENCODING).unwrap()
+        );
+
+        // tiny plain text bodies aren't worth compressing
+        let actual = get_gzip(SommStatsConfig::default(), "/v1/total-supply").await;
+        assert_eq!(StatusCode::OK, actual.status());
+        assert!(actual.headers().get(header::CONTENT_ENCODING).is_none());
+
+        let mut config = SommStatsConfig::default();
+        config.server.compression = false;
+        let actual = get_gzip(config, "/readyz").await;
+        assert!(actual.headers().get(header::CONTENT_ENCODING).is_none());
+    }
+
+    #[assay]
+    async fn rate_limit_rejects_excess_v1_requests() {
+        let mut config = SommStatsConfig::default();
+        config.server.rate_limit_per_sec = 1;
+        let router = router(Arc::new(config)).unwrap();
+        let request = |uri: &str| {
+            let mut request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            request
+                .extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from((Ipv4Addr::LOCALHOST, 1234))));
+            request
+        };
+
+        let actual = router
+            .clone()
+            .oneshot(request("/v1/total-supply"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, actual.status());
+
+        let actual = router
+            .clone()
+            .oneshot(request("/v1/total-supply"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::TOO_MANY_REQUESTS, actual.status());
+        assert_eq!("1", actual.headers().get(header::RETRY_AFTER).unwrap());
+
+        // the liveness route is exempt
+        let actual = router.oneshot(request("/")).await.unwrap();
+        assert_eq!(StatusCode::OK, actual.status());
+    }
+
+    #[assay]
+    async fn supply_endpoints_answer_head_with_cache_control() {
+        let mut config = SommStatsConfig::default();
+        config.cache.staking_update_period = 600;
+        let router = router(Arc::new(config)).unwrap();
+        let request = |method: Method, uri: &str| {
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let actual = router
+            .clone()
+            .oneshot(request(Method::HEAD, "/v1/total-supply"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, actual.status());
+        assert_eq!(
+            "public, max-age=3600",
+            actual.headers().get(header::CACHE_CONTROL).unwrap()
+        );
+        // hyper drops the body of HEAD responses when serving
+
+        BALANCES.write().await.insert(BONDED_KEY.to_string(), 1);
+        let actual = router
+            .clone()
+            .oneshot(request(Method::GET, "/v1/staked-supply"))
+            .await
+            .unwrap();
+        assert_eq!(
+            "public, max-age=600",
+            actual.headers().get(header::CACHE_CONTROL).unwrap()
+        );
+
+        // errors aren't cacheable
+        let actual = router
+            .oneshot(request(Method::GET, "/v1/circulating-supply"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, actual.status());
+        assert!(actual.headers().get(header::CACHE_CONTROL).is_none());
+    }
+
+    #[assay]
+    async fn chain_routes_serve_chain_balances() {
+        let mut config = SommStatsConfig::default();
+        let mut hub = ChainSection::default();
+        hub.accounting.foundation_addresses = vec!["cosmos1foundation".to_string()];
+        hub.accounting.vesting_addresses = vec!["cosmos1vesting".to_string()];
+        hub.accounting.total_supply_override = Some(1_000);
+        config.chains.insert("hub".to_string(), hub);
+        let router = router(Arc::new(config)).unwrap();
+        let request = |uri: &str| Request::builder().uri(uri).body(Body::empty()).unwrap();
+
+        let state = ChainState::get(Some("hub"));
+        update_balance(&state, COMMUNITY_POOL_KEY, 100).await;
+        update_balance(&state, "cosmos1foundation", 200).await;
+        update_balance(&state, "cosmos1vesting", 300).await;
+
+        let actual = router
+            .clone()
+            .oneshot(request("/v1/hub/circulating-supply?format=json"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, actual.status());
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: CirculatingSupplyResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(400, body.circulating_supply_usomm);
+
+        // the default chain's balances are kept apart
+        let actual = router
+            .clone()
+            .oneshot(request("/v1/circulating-supply"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, actual.status());
+
+        let actual = router
+            .clone()
+            .oneshot(request("/v1/osmosis/circulating-supply"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, actual.status());
+
+        let actual = router.oneshot(request("/readyz")).await.unwrap();
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: ReadinessResponse = serde_json::from_slice(&body).unwrap();
+        assert!(!body.missing.is_empty());
+        assert!(body.missing.iter().all(|k| !k.starts_with("hub/")));
+    }
+
+    #[test]
+    fn negotiate_representation() {
+        let none = SupplyParams::default();
+        let negotiate = |accept| Representation::negotiate(accept, &none);
+
+        assert_eq!(Representation::Text, negotiate(None));
+        assert_eq!(Representation::Text, negotiate(Some("*/*")));
+        assert_eq!(Representation::Text, negotiate(Some("image/png")));
+        assert_eq!(Representation::Json, negotiate(Some("application/json")));
+        assert_eq!(
+            Representation::Json,
+            negotiate(Some("text/plain;q=0.5, application/json"))
+        );
+        assert_eq!(
+            Representation::Text,
+            negotiate(Some("application/json;q=0.8, text/plain"))
+        );
+        // ties go to text
+        assert_eq!(
+            Representation::Text,
+            negotiate(Some("application/json, text/plain"))
+        );
+
+        let text = SupplyParams {
+            format: Some("text".to_string()),
+        };
+        assert_eq!(
+            Representation::Text,
+            Representation::negotiate(Some("application/json"), &text)
+        );
+    }
+
+    #[assay]
+    async fn get_total_supply_negotiates_json() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_static("application/json"));
+        let actual = get_total_supply(config(), Query(SupplyParams::default()), headers).await;
+
+        assert_eq!("accept", actual.headers().get(header::VARY).unwrap());
+        assert_eq!(
+            "application/json",
+            actual.headers().get(header::CONTENT_TYPE).unwrap()
+        );
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: TotalSupplyResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(TOTAL_USOMM_SUPPLY, body.total_supply_usomm);
+    }
+
+    #[test]
+    fn format_decimal_keeps_fraction() {
+        assert_eq!("1.5", format_decimal(1_500_000, 6));
+        assert_eq!("1", format_decimal(1_000_000, 6));
+        assert_eq!("0.000001", format_decimal(1, 6));
+        assert_eq!("123.000456", format_decimal(123_000_456, 6));
+        assert_eq!("42", format_decimal(42, 0));
+    }
+
+    #[assay]
+    async fn get_staked_supply_service_unavailable() {
+        let actual = get_staked_supply(config(), Query(SupplyParams::default()), HeaderMap::new())
+            .await
+            .into_response();
+
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, actual.status());
+    }
+
+    #[assay]
+    async fn get_bonded_ratio_from_cached_bonded_balance() {
+        let actual = get_bonded_ratio(config(), Query(SupplyParams::default()), HeaderMap::new())
+            .await
+            .into_response();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, actual.status());
+
+        BALANCES
+            .write()
+            .await
+            .insert(BONDED_KEY.to_string(), TOTAL_USOMM_SUPPLY / 4);
+        let actual = get_bonded_ratio(config(), Query(SupplyParams::default()), HeaderMap::new())
+            .await
+            .into_response();
+        assert_eq!(StatusCode::OK, actual.status());
+
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        assert_eq!("0.2500".as_bytes(), body);
+
+        let params = SupplyParams {
+            format: Some("json".to_string()),
+        };
+        let actual = get_bonded_ratio(config(), Query(params), HeaderMap::new())
+            .await
+            .into_response();
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: BondedRatioResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(0.25, body.bonded_ratio);
+
+        // not a cache miss, a zero total supply is rejected by the config validation
+        let mut config = SommStatsConfig::default();
+        config.accounting.total_supply_override = Some(0);
+        let actual = get_bonded_ratio(
+            State(Arc::new(config)),
+            Query(SupplyParams::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .into_response();
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, actual.status());
+    }
+
+    #[assay]
+    async fn get_community_pool_json() {
+        let actual = get_community_pool(config(), Query(SupplyParams::default()), HeaderMap::new())
+            .await
+            .into_response();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, actual.status());
+
+        BALANCES
+            .write()
+            .await
+            .insert(COMMUNITY_POOL_KEY.to_string(), 2_500_000);
+        let params = SupplyParams {
+            format: Some("json".to_string()),
+        };
+        let actual = get_community_pool(config(), Query(params), HeaderMap::new())
+            .await
+            .into_response();
+        assert_eq!(StatusCode::OK, actual.status());
+
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: CommunityPoolResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(2_500_000, body.community_pool_usomm);
+        // the fraction isn't truncated
+        assert_eq!("2.5", body.community_pool);
+    }
+
+    #[assay]
+    async fn get_circulating_supply_configured_accounts() {
+        let mut config = SommStatsConfig::default();
+        config.accounting.foundation_addresses = vec!["somm1foundation".to_string()];
+        config.accounting.vesting_addresses = vec!["somm1vesting".to_string()];
+        {
+            let mut balances = BALANCES.write().await;
+            balances.insert("somm1foundation".to_string(), 1_000_000);
+            balances.insert("somm1vesting".to_string(), 2_000_000);
+            balances.insert(COMMUNITY_POOL_KEY.to_string(), 3_000_000);
+        }
+
+        let params = SupplyParams {
+            format: Some("json".to_string()),
+        };
+        let actual =
+            get_circulating_supply(State(Arc::new(config)), Query(params), HeaderMap::new())
+                .await
+                .into_response();
+        assert_eq!(StatusCode::OK, actual.status());
+
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: CirculatingSupplyResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            TOTAL_USOMM_SUPPLY - 6_000_000,
+            body.circulating_supply_usomm
+        );
+    }
+
+    #[assay]
+    async fn get_circulating_supply_keeps_fraction() {
+        let mut config = SommStatsConfig::default();
+        config.accounting.foundation_addresses = vec!["somm1foundation".to_string()];
+        config.accounting.vesting_addresses = vec!["somm1vesting".to_string()];
+        {
+            let mut balances = BALANCES.write().await;
+            balances.insert(
+                "somm1foundation".to_string(),
+                TOTAL_USOMM_SUPPLY - 1_500_000,
+            );
+            balances.insert("somm1vesting".to_string(), 0);
+            balances.insert(COMMUNITY_POOL_KEY.to_string(), 0);
+        }
+
+        let actual = get_circulating_supply(
+            State(Arc::new(config)),
+            Query(SupplyParams::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .into_response();
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        assert_eq!("1.5".as_bytes(), body);
+    }
+
+    #[assay]
+    async fn get_circulating_supply_exceeds_total() {
+        let mut config = SommStatsConfig::default();
+        config.accounting.foundation_addresses = vec!["somm1foundation".to_string()];
+        config.accounting.vesting_addresses = vec!["somm1vesting".to_string()];
+        {
+            let mut balances = BALANCES.write().await;
+            balances.insert("somm1foundation".to_string(), TOTAL_USOMM_SUPPLY);
+            balances.insert("somm1vesting".to_string(), u64::MAX);
+            balances.insert(COMMUNITY_POOL_KEY.to_string(), 1);
+        }
+        let config = Arc::new(config);
+
+        assert!(matches!(
+            calculate_circulating_supply(&config, &*BALANCES.read().await),
+            Err(SupplyError::ExceedsTotal(_))
+        ));
+        let actual = get_circulating_supply(
+            State(config),
+            Query(SupplyParams::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .into_response();
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, actual.status());
+    }
+
+    #[assay]
+    async fn get_circulating_supply_skips_excluded_accounts() {
+        let mut config = SommStatsConfig::default();
+        config.accounting.foundation_addresses = vec!["somm1foundation".to_string()];
+        config.accounting.vesting_addresses =
+            vec!["somm1vesting".to_string(), "somm1excluded".to_string()];
+        config.accounting.exclude_from_circulating = vec!["somm1excluded".to_string()];
+        {
+            let mut balances = BALANCES.write().await;
+            balances.insert("somm1foundation".to_string(), 1);
+            balances.insert("somm1vesting".to_string(), 2);
+            balances.insert("somm1excluded".to_string(), 1_000_000);
+            balances.insert(COMMUNITY_POOL_KEY.to_string(), 3);
+        }
+
+        assert_eq!(
+            Ok(TOTAL_USOMM_SUPPLY - 6),
+            calculate_circulating_supply(&config, &*BALANCES.read().await)
+        );
+
+        // an excluded account that isn't cached doesn't hold up the calculation either
+        BALANCES.write().await.remove("somm1excluded");
+        assert_eq!(
+            Ok(TOTAL_USOMM_SUPPLY - 6),
+            calculate_circulating_supply(&config, &*BALANCES.read().await)
+        );
+
+        let breakdown = supply_breakdown(&config, &*BALANCES.read().await);
+        assert!(breakdown.complete);
+        assert_eq!(
+            vec!["somm1vesting"],
+            breakdown.vesting.keys().collect::<Vec<_>>()
+        );
+    }
+
+    #[assay]
+    async fn get_supply_breakdown_partial() {
+        BALANCES
+            .write()
+            .await
+            .insert(FOUNDATION_ADDRESS.to_string(), 1_000_000);
+
+        let actual = get_supply_breakdown(config(), Query(BreakdownParams::default()))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, actual.status());
+
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: SupplyBreakdownResponse = serde_json::from_slice(&body).unwrap();
+        assert!(!body.complete);
+        assert_eq!(
+            Some(&Some(1_000_000)),
+            body.foundation.get(FOUNDATION_ADDRESS)
+        );
+        assert_eq!(Some(&None), body.foundation.get(FOUNDATION_ADDRESS_2));
+        assert_eq!(None, body.community_pool);
+        assert_eq!(None, body.circulating_supply);
+        assert_eq!(VESTING_ACCOUNTS.len(), body.vesting.len());
+    }
+
+    #[assay]
+    async fn get_supply_breakdown_rejects_height_when_disabled() {
+        let actual = get_supply_breakdown(config(), Query(BreakdownParams { height: Some(100) }))
+            .await
+            .into_response();
+        assert_eq!(StatusCode::BAD_REQUEST, actual.status());
+    }
+
+    #[assay]
+    async fn get_readiness_reports_missing_keys() {
+        let mut config = SommStatsConfig::default();
+        config.accounting.foundation_addresses = vec!["somm1foundation".to_string()];
+        config.accounting.vesting_addresses = vec!["somm1vesting".to_string()];
+        let config = Arc::new(config);
+        BALANCES
+            .write()
+            .await
+            .insert("somm1foundation".to_string(), 1);
+
+        let actual = get_readiness(State(config.clone())).await;
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, actual.status());
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: ReadinessResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(vec![COMMUNITY_POOL_KEY, "somm1vesting"], body.missing);
+
+        {
+            let mut balances = BALANCES.write().await;
+            balances.insert(COMMUNITY_POOL_KEY.to_string(), 1);
+            balances.insert("somm1vesting".to_string(), 1);
+        }
+        let actual = get_readiness(State(config)).await;
+        assert_eq!(StatusCode::OK, actual.status());
+    }
+
+    #[assay]
+    async fn get_total_supply_text() {
+        let actual =
+            get_total_supply(config(), Query(SupplyParams::default()), HeaderMap::new()).await;
+        assert_eq!(StatusCode::OK, actual.status());
+
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        assert_eq!("499999979.9".as_bytes(), body);
+    }
+
+    #[assay]
+    async fn get_total_supply_uses_configured_decimals() {
+        let mut config = SommStatsConfig::default();
+        config.grpc.decimals = 0;
+        let actual = get_total_supply(
+            State(Arc::new(config)),
+            Query(SupplyParams::default()),
+            HeaderMap::new(),
+        )
+        .await;
+
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        assert_eq!(TOTAL_USOMM_SUPPLY.to_string().as_bytes(), body);
+    }
+
+    #[assay]
+    async fn total_supply_override_takes_precedence() {
+        let mut config = SommStatsConfig::default();
+        config.accounting.foundation_addresses = vec!["somm1foundation".to_string()];
+        config.accounting.vesting_addresses = vec!["somm1vesting".to_string()];
+        config.accounting.total_supply_override = Some(10_000_000);
+        let config = Arc::new(config);
+        {
+            let mut balances = BALANCES.write().await;
+            balances.insert("somm1foundation".to_string(), 1_000_000);
+            balances.insert("somm1vesting".to_string(), 2_000_000);
+            balances.insert(COMMUNITY_POOL_KEY.to_string(), 3_000_000);
+        }
+
+        let actual = get_circulating_supply(
+            State(config.clone()),
+            Query(SupplyParams::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .into_response();
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        assert_eq!("4".as_bytes(), body);
+
+        let actual = get_total_supply(
+            State(config),
+            Query(SupplyParams::default()),
+            HeaderMap::new(),
+        )
+        .await;
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        assert_eq!("10".as_bytes(), body);
+    }
+
+    #[assay]
+    async fn get_account_balance_validates_address() {
+        for address in [
+            "somm1invalid",
+            "cosmos1qypqxpq9qcrsszg2pvxq6rs0zqg3yyc5lzv7xu",
+        ] {
+            let actual = get_account_balance(config(), Path(address.to_string()))
+                .await
+                .into_response();
+            assert_eq!(StatusCode::BAD_REQUEST, actual.status());
+        }
+    }
+
+    async fn poll_once(source: &MockSource, config: &SommStatsConfig) {
+        for address in config.accounting.foundation_accounts() {
+            let _ = update_foundation_balance(source, config, &address).await;
+        }
+        for address in config.accounting.vesting_accounts() {
+            let _ = update_vesting_balance(source, config, &address).await;
+        }
+        let _ = update_community_pool_balance(source, config).await;
+    }
+
+    #[assay]
+    async fn get_circulating_supply_from_polled_balances() {
+        let mut config = SommStatsConfig::default();
+        config.grpc.endpoints = vec!["http://mock".to_string()];
+        config.accounting.foundation_addresses = vec!["somm1foundation".to_string()];
+        config.accounting.vesting_addresses = vec!["somm1vesting".to_string()];
+        let config = Arc::new(config);
+        let mut source = MockSource::default();
+        source
+            .balances
+            .insert("somm1foundation".to_string(), 1_000_000);
+        source
+            .balances
+            .insert("somm1vesting".to_string(), 2_000_000);
+
+        // the community pool query fails
+        poll_once(&source, &config).await;
+        let actual = get_circulating_supply(
+            State(config.clone()),
+            Query(SupplyParams::default()),
+            HeaderMap::new(),
+        )
+        .await
+        .into_response();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, actual.status());
+
+        source
+            .balances
+            .insert(COMMUNITY_POOL_KEY.to_string(), 3_000_000);
+        poll_once(&source, &config).await;
+        let params = SupplyParams {
+            format: Some("json".to_string()),
+        };
+        let actual = get_circulating_supply(State(config), Query(params), HeaderMap::new())
+            .await
+            .into_response();
+        assert_eq!(StatusCode::OK, actual.status());
+
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: CirculatingSupplyResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            TOTAL_USOMM_SUPPLY - 6_000_000,
+            body.circulating_supply_usomm
+        );
+        assert_eq!(Some(100), body.observed_at_height);
+        assert!(!body.stale);
+    }
+
+    #[assay]
+    async fn get_healthz_reports_unreachable_endpoints() {
+        let mut config = SommStatsConfig::default();
+        // nothing listens on the discard port
+        config.grpc.endpoints = vec!["http://127.0.0.1:9".to_string()];
+
+        let actual = get_healthz(State(Arc::new(config))).await;
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, actual.status());
+
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: HealthzResponse = serde_json::from_slice(&body).unwrap();
+        assert!(!body.healthy);
+        assert!(body.reachable.is_empty());
+        assert_eq!(vec!["http://127.0.0.1:9"], body.unreachable);
+    }
+
+    #[assay]
+    async fn get_healthz_reuses_recent_probe() {
+        let mut config = SommStatsConfig::default();
+        config.grpc.endpoints = vec!["http://127.0.0.1:9".to_string()];
+        let config = Arc::new(config);
+        let probe = |probed_at| EndpointProbe {
+            endpoints: config.grpc.endpoints.clone(),
+            probed_at,
+            reachable: config.grpc.endpoints.clone(),
+            unreachable: vec![],
+        };
+
+        *ENDPOINT_PROBE.write().await = Some(probe(Instant::now()));
+        let actual = get_healthz(State(config.clone())).await;
+        assert_eq!(StatusCode::OK, actual.status());
+
+        let expired = Instant::now() - Duration::from_secs(PROBE_TTL_SECS);
+        *ENDPOINT_PROBE.write().await = Some(probe(expired));
+        let actual = get_healthz(State(config)).await;
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, actual.status());
+    }
+
+    #[assay]
+    async fn get_vesting_totals() {
+        let mut config = SommStatsConfig::default();
+        config.accounting.vesting_addresses =
+            vec!["somm1vesting".to_string(), "somm1vesting2".to_string()];
+        let config = Arc::new(config);
+        BALANCES
+            .write()
+            .await
+            .insert("somm1vesting".to_string(), 2_500_000);
+
+        let actual = get_vesting(State(config.clone())).await;
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: VestingResponse = serde_json::from_slice(&body).unwrap();
+        assert!(!body.complete);
+        assert_eq!(1, body.accounts.len());
+        assert_eq!("2.5", body.accounts[0].locked_somm);
+
+        BALANCES
+            .write()
+            .await
+            .insert("somm1vesting2".to_string(), 1_000_000);
+
+        let actual = get_vesting(State(config)).await;
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: VestingResponse = serde_json::from_slice(&body).unwrap();
+        assert!(body.complete);
+        assert_eq!(3_500_000, body.total_locked_usomm);
+        assert_eq!(3, body.total_locked);
+        assert_eq!("3.5", body.total_locked_somm);
+    }
+
+    #[assay]
+    async fn get_account_balance_no_grpc_endpoints() {
+        let actual = get_account_balance(config(), Path(FOUNDATION_ADDRESS.to_string()))
+            .await
+            .into_response();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, actual.status());
+
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(NO_GRPC_ENDPOINTS, body.error.code);
+    }
+
+    #[assay]
+    async fn json_response_reports_serialization_failures() {
+        // JSON object keys must be strings
+        let body: HashMap<(u8, u8), u8> = HashMap::from([((1, 2), 3)]);
+        let actual = json_response(&body);
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, actual.status());
+
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(INTERNAL_ERROR, body.error.code);
+    }
+
+    #[assay]
+    async fn error_responses_have_json_body() {
+        let actual = get_staked_supply(config(), Query(SupplyParams::default()), HeaderMap::new())
+            .await
+            .into_response();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, actual.status());
+        assert_eq!(
+            "application/json",
+            actual.headers()[header::CONTENT_TYPE].to_str().unwrap()
+        );
+
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(CACHE_INCOMPLETE, body["error"]["code"]);
+        assert_eq!("bonded balance is not cached yet", body["error"]["message"]);
+    }
+
+    #[assay]
+    async fn get_account_balance_cached() {
+        LOOKUP_BALANCES
+            .write()
+            .await
+            .insert(FOUNDATION_ADDRESS.to_string(), (2_500_000, Instant::now()));
+
+        let actual = get_account_balance(config(), Path(FOUNDATION_ADDRESS.to_string()))
+            .await
+            .into_response();
+        assert_eq!(StatusCode::OK, actual.status());
+
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: AccountBalanceResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(2_500_000, body.balance_usomm);
+        assert_eq!("2.5", body.balance);
+    }
+
+    #[assay]
+    async fn post_balances_reports_errors_per_address() {
+        LOOKUP_BALANCES
+            .write()
+            .await
+            .insert(FOUNDATION_ADDRESS.to_string(), (2_500_000, Instant::now()));
+        let addresses = vec![
+            FOUNDATION_ADDRESS.to_string(),
+            FOUNDATION_ADDRESS.to_string(),
+            "cosmos1invalid".to_string(),
+            FOUNDATION_ADDRESS_2.to_string(),
+        ];
+
+        let actual = post_balances(config(), Json(addresses))
+            .await
+            .into_response();
+        assert_eq!(StatusCode::OK, actual.status());
+
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: BulkBalancesResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(1, body.balances.len());
+        assert_eq!(2_500_000, body.balances[FOUNDATION_ADDRESS]);
+        assert_eq!(INVALID_ADDRESS, body.errors["cosmos1invalid"].code);
+        // the default config has no endpoints to query uncached balances from
+        assert_eq!(NO_GRPC_ENDPOINTS, body.errors[FOUNDATION_ADDRESS_2].code);
+    }
+
+    #[assay]
+    async fn post_balances_rejects_oversized_batches() {
+        let mut config = SommStatsConfig::default();
+        config.server.max_bulk_addresses = 1;
+        let addresses = vec![
+            FOUNDATION_ADDRESS.to_string(),
+            FOUNDATION_ADDRESS_2.to_string(),
+        ];
+
+        let actual = post_balances(State(Arc::new(config)), Json(addresses))
+            .await
+            .into_response();
+        assert_eq!(StatusCode::BAD_REQUEST, actual.status());
+    }
+
+    #[assay]
+    async fn get_supply_freshness_ages() {
+        BALANCES_UPDATED_AT.write().await.insert(
+            COMMUNITY_POOL_KEY.to_string(),
+            SystemTime::now() - Duration::from_secs(120),
+        );
+
+        let actual = get_supply_freshness(config()).await;
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: BTreeMap<String, Option<u64>> = serde_json::from_slice(&body).unwrap();
+
+        assert!(body[COMMUNITY_POOL_KEY].unwrap() >= 120);
+        assert_eq!(None, body[FOUNDATION_ADDRESS]);
+    }
+
+    #[assay]
+    async fn get_version_reports_build_info() {
+        let actual = get_version().await;
+        assert_eq!(StatusCode::OK, actual.status());
+
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: VersionResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(env!("CARGO_PKG_VERSION"), body.version);
+        assert!(!body.git_commit.is_empty());
+        assert!(body.build_timestamp > 0);
+    }
+
+    #[assay]
+    async fn get_openapi_lists_routes() {
+        let actual = get_openapi(config()).await;
+        assert_eq!(StatusCode::OK, actual.status());
+
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        for path in ["/v1/circulating-supply", "/v1/balance/{address}", "/readyz"] {
+            assert!(body["paths"][path].is_object(), "{path} missing");
+        }
+        assert!(body["components"]["schemas"]["CirculatingSupplyResponse"].is_object());
+        assert!(body["servers"].is_null());
+    }
+
+    #[assay]
+    fn openapi_documents_every_v1_route() {
+        // the routes are read from the router's source, so a route added there without a
+        // #[utoipa::path] annotation listed in ApiDoc fails this test
+        let source = include_str!("server.rs");
+        let start = source.find("pub fn router(").unwrap();
+        let end = start + source[start..].find("\n}\n").unwrap();
+        let routes: Vec<String> = source[start..end]
+            .split('"')
+            .skip(1)
+            .step_by(2)
+            .filter_map(|literal| {
+                literal
+                    .strip_prefix("{prefix}")
+                    .map(|path| format!("/v1{path}"))
+                    .or_else(|| literal.starts_with("/v1/").then(|| literal.to_string()))
+            })
+            .filter(|path| !path.contains("{name}"))
+            .map(|path| {
+                path.split('/')
+                    .map(|segment| match segment.strip_prefix(':') {
+                        Some(param) => format!("{{{param}}}"),
+                        None => segment.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .collect();
+        assert!(routes.len() >= 12, "{routes:?}");
+
+        let paths = ApiDoc::openapi().paths.paths;
+        for route in routes {
+            assert!(paths.contains_key(&route), "{route} missing from ApiDoc");
+        }
+    }
+
+    #[assay]
+    async fn get_openapi_uses_public_base_url() {
+        let mut config = SommStatsConfig::default();
+        config.server.public_base_url = Some("https://stats.example.com/".to_string());
+
+        let actual = get_openapi(State(Arc::new(config))).await;
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!("https://stats.example.com", body["servers"][0]["url"]);
+    }
+
+    #[assay]
+    async fn admin_refresh_requires_token() {
+        let request = |cache: &str, token: Option<&str>| {
+            let mut builder = Request::builder()
+                .method(Method::POST)
+                .uri(format!("/admin/refresh/{cache}"));
+            if let Some(token) = token {
+                builder = builder.header(header::AUTHORIZATION, format!("Bearer {token}"));
+            }
+            builder.body(Body::empty()).unwrap()
+        };
+        let actual = router(Arc::new(SommStatsConfig::default()))
+            .unwrap()
+            .oneshot(request("balances", Some("secret")))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, actual.status());
+
+        let mut config = SommStatsConfig::default();
+        config.server.admin_token = Some("secret".to_string());
+        let router = router(Arc::new(config)).unwrap();
+        for token in [None, Some("wrong"), Some("secre")] {
+            let actual = router
+                .clone()
+                .oneshot(request("balances", token))
+                .await
+                .unwrap();
+            assert_eq!(StatusCode::UNAUTHORIZED, actual.status());
+        }
+
+        let actual = router
+            .oneshot(request("bids", Some("secret")))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, actual.status());
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(NOT_FOUND, body.error.code);
+    }
+
+    #[assay]
+    async fn debug_caches_only_routed_when_enabled() {
+        let request = || {
+            Request::builder()
+                .uri("/debug/caches")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let actual = router(Arc::new(SommStatsConfig::default()))
+            .unwrap()
+            .oneshot(request())
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, actual.status());
+
+        update_balance(&ChainState::get(None), COMMUNITY_POOL_KEY, 5).await;
+        BALANCES.write().await.insert(BONDED_KEY.to_string(), 7);
+        let mut config = SommStatsConfig::default();
+        config.server.debug_endpoints = true;
+        let actual = router(Arc::new(config))
+            .unwrap()
+            .oneshot(request())
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, actual.status());
+
+        let body = hyper::body::to_bytes(actual.into_body()).await.unwrap();
+        let body: DebugCachesResponse = serde_json::from_slice(&body).unwrap();
+        let community_pool = &body.balances[COMMUNITY_POOL_KEY];
+        assert_eq!(5, community_pool.value);
+        assert!(!community_pool.stale);
+        let bonded = &body.balances[BONDED_KEY];
+        assert_eq!(None, bonded.age_secs);
+        assert!(bonded.stale);
+    }
 }
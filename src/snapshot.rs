@@ -0,0 +1,185 @@
+//! Cache snapshots
+//!
+//! The balances cache is persisted to disk so that a restarted instance can serve the circulating
+//! supply right away instead of waiting for every poller to complete a cycle.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use abscissa_core::tracing::log::{debug, error, info, warn};
+use abscissa_tokio::tokio;
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    application::{ChainState, BALANCES},
+    history::record_supply_sample,
+    reload::current_config,
+};
+
+/// Version of the snapshot schema. Bump this whenever the balance keys or values change meaning so
+/// that snapshots written by older versions aren't loaded.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Serialized contents of the balances cache
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Snapshot {
+    /// Schema version. Snapshots written before versioning was added have none and load as 0.
+    #[serde(default)]
+    pub version: u32,
+    pub balances: HashMap<String, u64>,
+    /// Balances of the chains under `[chains]`, keyed by chain name
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub chains: HashMap<String, HashMap<String, u64>>,
+}
+
+/// Writes the current contents of the balances cache to the snapshot file. The snapshot is written
+/// to a sibling temp file and renamed into place so that a crash mid-write never leaves a truncated
+/// snapshot behind. Nothing is written if the cache is empty, so that an existing snapshot isn't
+/// replaced with nothing.
+pub async fn take_cache_snapshot(path: &Path) -> Result<()> {
+    let mut chains = HashMap::new();
+    for state in ChainState::named() {
+        let balances = state.balances.read().await;
+        if let (Some(name), false) = (state.name, balances.is_empty()) {
+            chains.insert(name, balances.clone());
+        }
+    }
+    let snapshot = Snapshot {
+        version: SNAPSHOT_VERSION,
+        balances: BALANCES.read().await.clone(),
+        chains,
+    };
+    if snapshot.balances.is_empty() && snapshot.chains.is_empty() {
+        debug!("balances cache is empty, skipping snapshot");
+        return Ok(());
+    }
+
+    let tmp_path = tmp_path(path);
+    fs::write(&tmp_path, serde_json::to_string(&snapshot)?)?;
+    fs::rename(&tmp_path, path)?;
+    info!("wrote cache snapshot to {}", path.display());
+
+    Ok(())
+}
+
+/// Loads the snapshot file into the balances cache if one exists. Snapshots with a different
+/// schema version are ignored and the cache starts empty.
+pub async fn try_load_snapshot(path: &Path) -> Result<()> {
+    if !path.exists() {
+        info!("no cache snapshot found at {}", path.display());
+        return Ok(());
+    }
+
+    let snapshot: Snapshot = serde_json::from_str(&fs::read_to_string(path)?)?;
+    if snapshot.version != SNAPSHOT_VERSION {
+        warn!(
+            "ignoring cache snapshot {} with version {}, expected {}",
+            path.display(),
+            snapshot.version,
+            SNAPSHOT_VERSION
+        );
+        return Ok(());
+    }
+    info!(
+        "loaded {} balances from cache snapshot {}",
+        snapshot.balances.len(),
+        path.display()
+    );
+    BALANCES.write().await.extend(snapshot.balances);
+    for (name, balances) in snapshot.chains {
+        ChainState::get(Some(&name))
+            .balances
+            .write()
+            .await
+            .extend(balances);
+    }
+
+    Ok(())
+}
+
+/// Periodically writes the balances cache to the snapshot file
+pub async fn poll_snapshot() -> Result<()> {
+    let config = current_config();
+    let period = config.cache.snapshot_interval_secs;
+    if period == 0 {
+        debug!("periodic cache snapshots are disabled");
+        return Ok(());
+    }
+    debug!("taking cache snapshot every {} seconds", period);
+
+    let path = Path::new(&config.cache.snapshot_file);
+    loop {
+        // the cache was just loaded from the snapshot at startup, so wait a period before writing
+        tokio::time::sleep(Duration::from_secs(period)).await;
+        take_cache_snapshot(path)
+            .await
+            .unwrap_or_else(|e| error!("failed to take cache snapshot: {:?}", e));
+        record_supply_sample(&current_config(), SystemTime::now()).await;
+    }
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    tmp.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::DEFAULT_SNAPSHOT_FILE, query::update_balance};
+    use assay::assay;
+
+    #[assay]
+    async fn snapshot_round_trip() {
+        let path = Path::new(DEFAULT_SNAPSHOT_FILE);
+        let default_chain = ChainState::get(None);
+        let hub = ChainState::get(Some("hub"));
+        update_balance(&default_chain, "somm1foundation", 1_000_000).await;
+        update_balance(&default_chain, "communitypool", u64::MAX).await;
+        update_balance(&hub, "communitypool", 1).await;
+        take_cache_snapshot(path).await.unwrap();
+        assert!(!tmp_path(path).exists());
+
+        let expected = BALANCES.read().await.clone();
+        BALANCES.write().await.clear();
+        hub.balances.write().await.clear();
+        try_load_snapshot(path).await.unwrap();
+
+        assert_eq!(expected, *BALANCES.read().await);
+        assert_eq!(Some(&1), hub.balances.read().await.get("communitypool"));
+    }
+
+    #[assay]
+    async fn take_cache_snapshot_skips_empty_cache() {
+        let path = Path::new(DEFAULT_SNAPSHOT_FILE);
+        take_cache_snapshot(path).await.unwrap();
+
+        assert!(!path.exists());
+    }
+
+    #[assay]
+    async fn try_load_snapshot_ignores_other_versions() {
+        let path = Path::new(DEFAULT_SNAPSHOT_FILE);
+        // written before snapshots were versioned
+        fs::write(path, r#"{"balances":{"communitypool":1000000}}"#).unwrap();
+        try_load_snapshot(path).await.unwrap();
+
+        assert!(BALANCES.read().await.is_empty());
+    }
+
+    #[assay]
+    async fn try_load_snapshot_missing_file() {
+        try_load_snapshot(Path::new(DEFAULT_SNAPSHOT_FILE))
+            .await
+            .unwrap();
+
+        assert!(BALANCES.read().await.is_empty());
+    }
+}
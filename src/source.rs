@@ -0,0 +1,191 @@
+//! Balance sources
+//!
+//! The pollers read balances through [`BalanceSource`] rather than calling the gRPC queries
+//! directly, so the accounting can be exercised in tests against a source with controlled
+//! balances instead of a live node.
+
+use eyre::Result;
+use futures::{future::BoxFuture, FutureExt};
+
+use crate::query::{
+    query_balance, query_block_time, query_bonded_balance, query_community_pool_balance,
+    query_latest_height, query_vesting_balance,
+};
+
+/// A queried amount with the block height the node reported answering at, if it reported one
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Observed {
+    pub amount: u64,
+    pub height: Option<u64>,
+}
+
+/// A past block to read state at
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PastBlock {
+    pub height: u64,
+    /// Block time as a Unix timestamp
+    pub time: i64,
+}
+
+/// Queries the on-chain values the balances cache is built from. Each query targets a single
+/// endpoint, racing endpoints and retrying is left to the caller. Queries taking a `height` read
+/// the state at that block height, or at the latest height if `None`.
+pub trait BalanceSource: Send + Sync {
+    /// Balance of an address in `denom`
+    fn balance<'a>(
+        &'a self,
+        endpoint: &'a str,
+        address: &'a str,
+        denom: &'a str,
+        height: Option<u64>,
+    ) -> BoxFuture<'a, Result<Observed>>;
+
+    /// Community pool balance in `denom`
+    fn community_pool_balance<'a>(
+        &'a self,
+        endpoint: &'a str,
+        denom: &'a str,
+        height: Option<u64>,
+    ) -> BoxFuture<'a, Result<Observed>>;
+
+    /// Total bonded tokens in the staking pool
+    fn bonded_balance<'a>(&'a self, endpoint: &'a str) -> BoxFuture<'a, Result<Observed>>;
+
+    /// Locked portion of a vesting account's balance in `denom`, as of the block's time if `at` is
+    /// given or the current time otherwise
+    fn vesting_balance<'a>(
+        &'a self,
+        endpoint: &'a str,
+        address: &'a str,
+        denom: &'a str,
+        at: Option<PastBlock>,
+    ) -> BoxFuture<'a, Result<Observed>>;
+
+    /// Latest block height
+    fn latest_height<'a>(&'a self, endpoint: &'a str) -> BoxFuture<'a, Result<u64>>;
+
+    /// Time of the block at `height` as a Unix timestamp
+    fn block_time<'a>(&'a self, endpoint: &'a str, height: u64) -> BoxFuture<'a, Result<i64>>;
+}
+
+/// Queries balances from the chain over the pooled gRPC connections
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GrpcSource;
+
+impl BalanceSource for GrpcSource {
+    fn balance<'a>(
+        &'a self,
+        endpoint: &'a str,
+        address: &'a str,
+        denom: &'a str,
+        height: Option<u64>,
+    ) -> BoxFuture<'a, Result<Observed>> {
+        query_balance(endpoint, address, denom, height).boxed()
+    }
+
+    fn community_pool_balance<'a>(
+        &'a self,
+        endpoint: &'a str,
+        denom: &'a str,
+        height: Option<u64>,
+    ) -> BoxFuture<'a, Result<Observed>> {
+        query_community_pool_balance(endpoint, denom, height).boxed()
+    }
+
+    fn bonded_balance<'a>(&'a self, endpoint: &'a str) -> BoxFuture<'a, Result<Observed>> {
+        query_bonded_balance(endpoint).boxed()
+    }
+
+    fn vesting_balance<'a>(
+        &'a self,
+        endpoint: &'a str,
+        address: &'a str,
+        denom: &'a str,
+        at: Option<PastBlock>,
+    ) -> BoxFuture<'a, Result<Observed>> {
+        query_vesting_balance(endpoint, address, denom, at).boxed()
+    }
+
+    fn latest_height<'a>(&'a self, endpoint: &'a str) -> BoxFuture<'a, Result<u64>> {
+        query_latest_height(endpoint).boxed()
+    }
+
+    fn block_time<'a>(&'a self, endpoint: &'a str, height: u64) -> BoxFuture<'a, Result<i64>> {
+        query_block_time(endpoint, height).boxed()
+    }
+}
+
+/// Block height the mock source reports for every query
+#[cfg(test)]
+pub const MOCK_HEIGHT: u64 = 100;
+
+/// Serves fixed balances keyed like the balances cache, for tests. Keys without a balance fail.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MockSource {
+    pub balances: std::collections::HashMap<String, u64>,
+    /// Number of block times queried
+    pub block_time_queries: std::sync::atomic::AtomicUsize,
+}
+
+#[cfg(test)]
+impl MockSource {
+    fn get(&self, key: &str) -> BoxFuture<'_, Result<Observed>> {
+        let balance = self
+            .balances
+            .get(key)
+            .map(|&amount| Observed {
+                amount,
+                height: Some(MOCK_HEIGHT),
+            })
+            .ok_or_else(|| eyre::eyre!("no mock balance for {key}"));
+
+        futures::future::ready(balance).boxed()
+    }
+}
+
+#[cfg(test)]
+impl BalanceSource for MockSource {
+    fn balance<'a>(
+        &'a self,
+        _endpoint: &'a str,
+        address: &'a str,
+        _denom: &'a str,
+        _height: Option<u64>,
+    ) -> BoxFuture<'a, Result<Observed>> {
+        self.get(address)
+    }
+
+    fn community_pool_balance<'a>(
+        &'a self,
+        _endpoint: &'a str,
+        _denom: &'a str,
+        _height: Option<u64>,
+    ) -> BoxFuture<'a, Result<Observed>> {
+        self.get(crate::query::COMMUNITY_POOL_KEY)
+    }
+
+    fn bonded_balance<'a>(&'a self, _endpoint: &'a str) -> BoxFuture<'a, Result<Observed>> {
+        self.get(crate::query::BONDED_KEY)
+    }
+
+    fn vesting_balance<'a>(
+        &'a self,
+        _endpoint: &'a str,
+        address: &'a str,
+        _denom: &'a str,
+        _at: Option<PastBlock>,
+    ) -> BoxFuture<'a, Result<Observed>> {
+        self.get(address)
+    }
+
+    fn latest_height<'a>(&'a self, _endpoint: &'a str) -> BoxFuture<'a, Result<u64>> {
+        futures::future::ready(Ok(MOCK_HEIGHT)).boxed()
+    }
+
+    fn block_time<'a>(&'a self, _endpoint: &'a str, _height: u64) -> BoxFuture<'a, Result<i64>> {
+        self.block_time_queries
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        futures::future::ready(Ok(0)).boxed()
+    }
+}
@@ -0,0 +1,40 @@
+//! Prometheus metrics
+//!
+//! Counters and gauges are recorded throughout the app with the `metrics` macros, and rendered in
+//! the Prometheus text format by the `/metrics` route. If the recorder hasn't been installed the
+//! macros are no-ops.
+
+use eyre::Result;
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use once_cell::sync::OnceCell;
+
+pub const QUERY_SUCCESS_TOTAL: &str = "sommstats_query_success_total";
+pub const QUERY_FAILURE_TOTAL: &str = "sommstats_query_failure_total";
+pub const BALANCE_LAST_UPDATED: &str = "sommstats_balance_last_updated_timestamp_seconds";
+pub const CIRCULATING_SUPPLY: &str = "sommstats_circulating_supply_usomm";
+pub const UPDATE_DURATION_SECONDS: &str = "sommstats_update_duration_seconds";
+pub const CACHE_EXPIRED_TOTAL: &str = "sommstats_cache_expired_total";
+
+/// Buckets of the update duration histogram, spanning a single fast query to a query that
+/// exhausts its retries
+const UPDATE_DURATION_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+static PROMETHEUS: OnceCell<PrometheusHandle> = OnceCell::new();
+
+/// Installs the global Prometheus recorder
+pub fn install_recorder() -> Result<()> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Full(UPDATE_DURATION_SECONDS.to_string()),
+            UPDATE_DURATION_BUCKETS,
+        )?
+        .install_recorder()?;
+    PROMETHEUS
+        .set(handle)
+        .map_err(|_| eyre::eyre!("prometheus recorder already installed"))
+}
+
+/// Renders the current metrics, or `None` if the recorder hasn't been installed
+pub fn render() -> Option<String> {
+    PROMETHEUS.get().map(|h| h.render())
+}